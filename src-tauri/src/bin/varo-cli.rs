@@ -0,0 +1,5 @@
+// Headless command-line entry point: reuses VaroCore without creating a window
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(varo_lib::cli::run(args))
+}
@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+use crate::core::CoreOptions;
+use crate::utils::export::{ExportFormat, EXPORT_FORMATS};
+
+pub const USAGE: &str = "\
+Usage: varo-cli [OPTIONS] <COMMAND>
+
+Commands:
+  list-nodes          List the nodes available to the current user
+  list-presets        List all environment presets
+  launch <node-id>    Launch a node with the selected preset
+  plan <node-id>      Show what launching a node would run, without running it
+  export <format> [node-id]
+                      Print an activation script for the active presets (and node).
+                      Formats: bash (zsh), fish, powershell, cmd, dotenv
+  show-config         Print the merged configuration as JSON
+  validate            Check config, presets and nodes, exiting non-zero on errors
+
+Options:
+  --config <path>     Extra config file merged on top of all other config layers
+  --preset <id>       Preset to use instead of the last used or default preset.
+                      Repeat to stack presets, bottom layer first
+  --varo-path <path>  VARO_PATH to load nodes from
+  --json              Print command results as JSON
+  -o, --output <path> Write the export to a file instead of stdout
+  -v, --verbose       Print loader debug output to stderr
+  -h, --help          Print this help";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    ListNodes,
+    ListPresets,
+    Launch(String),
+    Plan(String),
+    Export { format: ExportFormat, node_id: Option<String> },
+    ShowConfig,
+    Validate,
+    Help,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CliArgs {
+    pub options: CoreOptions,
+    pub command: Option<CliCommand>,
+    pub json: bool,
+    pub output: Option<PathBuf>,
+    pub verbose: bool,
+}
+
+/// Parse command-line arguments (without the program name).
+/// Options may appear before or after the command.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<CliArgs, String> {
+    let mut parsed = CliArgs::default();
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        // Support both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+
+        let mut take_value = |name: &str| -> Result<String, String> {
+            inline_value.clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+
+        match flag.as_str() {
+            "--config" => parsed.options.config_path = Some(PathBuf::from(take_value("--config")?)),
+            "--preset" => parsed.options.preset_ids.push(take_value("--preset")?),
+            "--varo-path" => parsed.options.varo_path = Some(take_value("--varo-path")?),
+            "-o" | "--output" => parsed.output = Some(PathBuf::from(take_value("--output")?)),
+            "--json" => parsed.json = true,
+            "-v" | "--verbose" => parsed.verbose = true,
+            "-h" | "--help" => parsed.command = Some(CliCommand::Help),
+            other if other.starts_with('-') => return Err(format!("Unknown option: {}", other)),
+            _ => positional.push(arg),
+        }
+    }
+
+    if parsed.command == Some(CliCommand::Help) {
+        return Ok(parsed);
+    }
+
+    let mut positional = positional.into_iter();
+    parsed.command = match positional.next().as_deref() {
+        None => None,
+        Some("list-nodes") => Some(CliCommand::ListNodes),
+        Some("list-presets") => Some(CliCommand::ListPresets),
+        Some("launch") => {
+            let node_id = positional.next().ok_or("Missing <node-id> for launch")?;
+            Some(CliCommand::Launch(node_id))
+        }
+        Some("plan") => {
+            let node_id = positional.next().ok_or("Missing <node-id> for plan")?;
+            Some(CliCommand::Plan(node_id))
+        }
+        Some("export") => {
+            let format = positional.next().ok_or("Missing <format> for export")?;
+            let format = ExportFormat::parse(&format)
+                .ok_or_else(|| format!("Unknown export format '{}' (expected one of: {})", format, EXPORT_FORMATS.join(", ")))?;
+            Some(CliCommand::Export { format, node_id: positional.next() })
+        }
+        Some("show-config") => Some(CliCommand::ShowConfig),
+        Some("validate") => Some(CliCommand::Validate),
+        Some(other) => return Err(format!("Unknown command: {}", other)),
+    };
+
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument: {}", extra));
+    }
+
+    Ok(parsed)
+}
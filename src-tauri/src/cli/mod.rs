@@ -0,0 +1,272 @@
+pub mod args;
+
+use std::path::Path;
+use serde_json::{json, Value};
+use crate::core::config::ConfigManager;
+use crate::core::VaroCore;
+use crate::models::diagnostics::Diagnostic;
+use crate::models::entities::{EnvOrigin, VaroNode};
+use crate::utils::export::ExportFormat;
+use crate::utils::log::{set_log_mode, LogMode};
+
+pub use args::{parse_args, CliArgs, CliCommand, USAGE};
+
+/// Run the headless command-line interface and return the process exit code.
+/// `args` should not include the program name.
+pub fn run(args: Vec<String>) -> i32 {
+    let cli_args = match parse_args(args) {
+        Ok(cli_args) => cli_args,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    let command = match &cli_args.command {
+        Some(CliCommand::Help) => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Some(command) => command.clone(),
+        None => {
+            eprintln!("Error: Missing command\n\n{}", USAGE);
+            return 2;
+        }
+    };
+
+    // Keep stdout reserved for command results
+    set_log_mode(if cli_args.verbose { LogMode::Stderr } else { LogMode::Off });
+
+    let core = VaroCore::with_options(cli_args.options.clone());
+
+    if command != CliCommand::Validate {
+        if let Some(preset_id) = missing_presets(&core, &cli_args).first() {
+            eprintln!("Error: No EnvPreset found with id: {}", preset_id);
+            return 1;
+        }
+    }
+
+    let result = match command {
+        CliCommand::ListNodes => list_nodes(&core, cli_args.json),
+        CliCommand::ListPresets => list_presets(&core, cli_args.json),
+        CliCommand::Launch(node_id) => launch(&core, &node_id),
+        CliCommand::Plan(node_id) => plan(&core, &node_id, cli_args.json),
+        CliCommand::Export { format, node_id } => export(&core, format, node_id.as_deref(), cli_args.output.as_deref()),
+        CliCommand::ShowConfig => {
+            print_json(&core.sync_get_config());
+            Ok(())
+        }
+        CliCommand::Validate => validate(&core, &cli_args),
+        CliCommand::Help => Ok(()),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+/// `--preset` ids that did not end up in the active stack
+fn missing_presets(core: &VaroCore, cli_args: &CliArgs) -> Vec<String> {
+    let active_ids: Vec<String> = core.sync_get_active_presets().into_iter().map(|p| p.id).collect();
+    cli_args.options.preset_ids.iter()
+        .filter(|id| !active_ids.contains(id))
+        .cloned()
+        .collect()
+}
+
+fn print_json(value: &Value) {
+    match serde_json::to_string_pretty(value) {
+        Ok(text) => println!("{}", text),
+        Err(e) => eprintln!("Error: Failed to serialize output: {}", e),
+    }
+}
+
+/// Summary of a node for CLI output (icons are left out to keep output readable)
+fn node_summary(node: &VaroNode) -> Value {
+    json!({
+        "id": node.id,
+        "name": node.name,
+        "category": node.category,
+        "group_id": node.group_id,
+        "description": node.description,
+        "filepath": node.filepath,
+    })
+}
+
+fn list_nodes(core: &VaroCore, as_json: bool) -> Result<(), String> {
+    let nodes = core.sync_get_all_nodes();
+
+    if as_json {
+        print_json(&Value::Array(nodes.iter().map(node_summary).collect()));
+        return Ok(());
+    }
+
+    let id_width = nodes.iter().map(|n| n.id.len()).max().unwrap_or(0);
+    let name_width = nodes.iter().map(|n| n.name.len()).max().unwrap_or(0);
+    for node in &nodes {
+        println!("{:id_width$}  {:name_width$}  {}", node.id, node.name, node.category);
+    }
+    Ok(())
+}
+
+fn list_presets(core: &VaroCore, as_json: bool) -> Result<(), String> {
+    let presets = core.sync_get_all_presets();
+    let active_ids: Vec<String> = core.sync_get_active_presets().into_iter().map(|p| p.id).collect();
+    let layer_of = |id: &str| active_ids.iter().position(|active| active == id);
+
+    if as_json {
+        let items = presets.iter()
+            .map(|p| json!({
+                "id": p.id,
+                "name": p.name,
+                "description": p.description,
+                "filepath": p.filepath,
+                "extends": p.extends,
+                "selected": active_ids.last().map(String::as_str) == Some(p.id.as_str()),
+                "layer": layer_of(&p.id),
+            }))
+            .collect();
+        print_json(&Value::Array(items));
+        return Ok(());
+    }
+
+    // Active presets are marked with their layer number, 1 being the bottom layer
+    let id_width = presets.iter().map(|p| p.id.len()).max().unwrap_or(0);
+    for preset in &presets {
+        let marker = layer_of(&preset.id).map_or(String::new(), |layer| (layer + 1).to_string());
+        println!("{:>2} {:id_width$}  {}", marker, preset.id, preset.name);
+    }
+    Ok(())
+}
+
+fn launch(core: &VaroCore, node_id: &str) -> Result<(), String> {
+    core.sync_execute_node_and_wait(node_id).map_err(|e| e.to_string())
+}
+
+/// Print the launch plan for a node: environment changes, then each command
+fn plan(core: &VaroCore, node_id: &str, as_json: bool) -> Result<(), String> {
+    let plan = core.sync_plan_node_launch(node_id).map_err(|e| e.to_string())?;
+
+    if as_json {
+        print_json(&json!(plan));
+        return Ok(());
+    }
+
+    println!("Node:   {} ({})", plan.node_name, plan.node_id);
+    if plan.preset_ids.is_empty() {
+        println!("Preset: (none)");
+    } else {
+        println!("Preset: {}", plan.preset_ids.join(" > "));
+    }
+
+    println!("\nEnvironment:");
+    if plan.env.is_empty() {
+        println!("  (no changes)");
+    }
+    for var in &plan.env {
+        let origin = match (&var.origin, &var.preset_id) {
+            (EnvOrigin::Preset, Some(preset_id)) => preset_id.clone(),
+            (EnvOrigin::Preset, None) => "preset".to_string(),
+            (EnvOrigin::Node, _) => "node".to_string(),
+            (EnvOrigin::Override, _) => "override".to_string(),
+        };
+        match &var.value {
+            Some(value) => println!("  [{:<8}] {}={}", origin, var.name, value),
+            None => println!("  [{:<8}] unset {}", origin, var.name),
+        }
+    }
+
+    for error in &plan.errors {
+        println!("  error: {}", error);
+    }
+
+    for command in &plan.commands {
+        println!("\nCommand {} ({}{})", command.index + 1, command.path_type, if command.wait { ", wait" } else { "" });
+        match &command.program {
+            Some(program) => println!("  program: {}", program),
+            None => println!("  path:    {}", command.path),
+        }
+        if !command.args.is_empty() {
+            let argv: Vec<String> = command.args.iter().map(|a| format!("{:?}", a)).collect();
+            println!("  args:    {}", argv.join(" "));
+        }
+        if let Some(cwd) = &command.cwd {
+            println!("  cwd:     {}", cwd);
+        }
+        if !command.unresolved.is_empty() {
+            println!("  unresolved: {}", command.unresolved.iter().map(|t| format!("${{{}}}", t)).collect::<Vec<_>>().join(", "));
+        }
+        if let Some(error) = &command.error {
+            println!("  error:   {}", error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print an activation script, or write it to `output`
+fn export(core: &VaroCore, format: ExportFormat, node_id: Option<&str>, output: Option<&Path>) -> Result<(), String> {
+    let script = core.sync_export_env(node_id, format).map_err(|e| e.to_string())?;
+    match output {
+        Some(path) => std::fs::write(path, script)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e)),
+        None => {
+            print!("{}", script);
+            Ok(())
+        }
+    }
+}
+
+/// Check config, presets and node files, printing one line per diagnostic.
+/// Returns an error if any diagnostic is an error.
+fn validate(core: &VaroCore, cli_args: &CliArgs) -> Result<(), String> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    // Config layers (the core falls back to an empty config, so check the load directly)
+    if let Err(e) = ConfigManager::load_with_cli_config(cli_args.options.config_path.clone()) {
+        let file = cli_args.options.config_path.as_ref().and_then(|p| p.to_str());
+        diagnostics.push(Diagnostic::error(file, e.to_string()));
+    }
+
+    let active_presets = core.sync_get_active_presets();
+    for preset_id in missing_presets(core, cli_args) {
+        diagnostics.push(Diagnostic::error(None, format!("No EnvPreset found with id: {}", preset_id)));
+    }
+
+    // Preset and node load problems collected by the managers
+    diagnostics.extend(core.sync_get_diagnostics());
+
+    let presets = core.sync_get_all_presets().len();
+    let nodes = core.sync_get_all_nodes().len();
+    let error_count = diagnostics.iter().filter(|d| d.is_error()).count();
+
+    if cli_args.json {
+        print_json(&json!({
+            "valid": error_count == 0,
+            "presets": presets,
+            "nodes": nodes,
+            "selected_preset": active_presets.last().map(|p| p.id.clone()),
+            "active_presets": active_presets.iter().map(|p| p.id.clone()).collect::<Vec<_>>(),
+            "diagnostics": diagnostics,
+            "scan": core.sync_get_scan_stats(),
+        }));
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+        println!(
+            "{} presets, {} nodes, {} errors, {} warnings",
+            presets, nodes, error_count, diagnostics.len() - error_count
+        );
+    }
+
+    if error_count == 0 {
+        Ok(())
+    } else {
+        Err(format!("Validation failed with {} errors", error_count))
+    }
+}
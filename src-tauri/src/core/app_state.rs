@@ -1,28 +1,69 @@
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
 use tokio::sync::RwLock;
 use serde_json::Value;
 
-use crate::models::entities::{EnvPreset, VaroNode};
+use crate::models::entities::{CatalogDelta, CatalogSource, CatalogStatus, EnvPreset, LaunchInfo, LaunchPlan, NodeAccessReport, ScanStats, VaroNode};
+use crate::models::diagnostics::Diagnostic;
 use crate::models::errors::{VaroError, VaroResult};
+use crate::core::catalog::{CatalogCache, CatalogCacheSettings, LoadedCatalog};
+use crate::core::catalog::refresh::scan_error;
 use crate::core::config::ConfigManager;
 use crate::core::env_presets::PresetManager;
 use crate::core::nodes::NodeManager;
+use crate::core::processes::{LaunchEvent, LaunchLogChunk, LaunchLogSettings, ProcessRegistry};
 use crate::core::system::SystemInfo;
+use crate::core::watch::{CatalogSnapshot, FileChanges, FileWatcher, WatchSettings, WatchTarget};
+use crate::utils::config;
+use crate::utils::export::{render_env_script, ExportFormat};
+use crate::utils::icon::Icon;
+use crate::utils::log::varo_log;
+
+/// Startup overrides, typically passed on the command line
+#[derive(Debug, Clone, Default)]
+pub struct CoreOptions {
+    /// Extra config file merged on top of all other config layers (`--config`)
+    pub config_path: Option<PathBuf>,
+    /// Presets to activate instead of the last used or default ones, bottom layer first (`--preset`, repeatable)
+    pub preset_ids: Vec<String>,
+    /// VARO_PATH to load nodes from, overriding the system and preset value (`--varo-path`)
+    pub varo_path: Option<String>,
+    /// Start from the catalog saved by the last session and keep it up to date. The app does;
+    /// the CLI always scans.
+    pub catalog_cache: bool,
+}
 
 #[derive(Debug)]
 pub struct VaroCore {
     pub config_manager: Arc<RwLock<ConfigManager>>,
+    /// Locked after `node_manager` when both are held, so readers waiting behind queued writers
+    /// cannot block each other
     pub preset_manager: Arc<RwLock<PresetManager>>,
     pub node_manager: Arc<RwLock<NodeManager>>,
+    pub process_registry: ProcessRegistry,
     pub system_info: SystemInfo,
+    watcher: Mutex<Option<FileWatcher>>,
+    /// `None` when the catalog cache is not used
+    catalog_cache: Option<CatalogCacheSettings>,
+    catalog_status: Mutex<CatalogStatus>,
+    /// File changes seen while the catalog from the cache is shown, applied once it is replaced
+    deferred_changes: Mutex<FileChanges>,
+    /// Held while presets are reloaded, so scans that run providers outside the preset lock finish one at a time
+    preset_scan: Mutex<()>,
+    /// Held while nodes are reloaded, so scans that read files outside the node lock finish one at a time
+    node_scan: Mutex<()>,
 }
 
 impl VaroCore {
     pub fn new() -> Self {
+        Self::with_options(CoreOptions::default())
+    }
+
+    pub fn with_options(options: CoreOptions) -> Self {
         let system_info = SystemInfo::collect();
         
         // Load configuration file
-        let config_manager = match ConfigManager::load() {
+        let config_manager = match ConfigManager::load_with_cli_config(options.config_path.clone()) {
             Ok(manager) => manager,
             Err(e) => {
                 eprintln!("Warning: Failed to load config: {}", e);
@@ -30,50 +71,109 @@ impl VaroCore {
             }
         };
         
+        // Start from the catalog saved by the last session when there is one. It is shown as stale
+        // until `start_catalog_refresh` has scanned the real one.
+        let catalog_cache = Some(CatalogCacheSettings::from_config(&config_manager.get_section("catalog_cache")))
+            .filter(|settings| options.catalog_cache && settings.enabled);
+        let cached = catalog_cache.as_ref()
+            .and_then(|settings| settings.path.as_deref())
+            .and_then(CatalogCache::load);
+
         // Load env presets based on config
         let env_presets_config = config_manager.get_section("env_presets");
-        let preset_manager = match PresetManager::new(&env_presets_config) {
-            Ok(manager) => manager,
-            Err(e) => {
-                eprintln!("Warning: Failed to load presets: {}", e);
-                PresetManager::empty()
-            }
+        let mut preset_manager = match &cached {
+            Some(cache) => PresetManager::from_cached(&env_presets_config, cache.presets.clone()),
+            None => match PresetManager::new(&env_presets_config) {
+                Ok(manager) => manager,
+                Err(e) => {
+                    eprintln!("Warning: Failed to load presets: {}", e);
+                    PresetManager::empty()
+                }
+            },
         };
         
-        let mut node_manager = NodeManager::new();
+        let mut node_manager = NodeManager::new(&system_info);
+        node_manager.set_access_config(&config_manager.get_section("access"));
+        node_manager.set_nodes_config(&config_manager.get_section("nodes"));
+        node_manager.set_varo_path_override(options.varo_path.clone());
         
-        // Try to load nodes initially
-        if let Err(e) = node_manager.load_nodes_from_varo_path() {
-            eprintln!("Warning: Failed to load nodes: {}", e);
+        // Activate the requested, last used or default presets and load nodes through them,
+        // falling back to the system VARO_PATH when no preset applies
+        for id in &options.preset_ids {
+            if let Err(e) = preset_manager.push_preset(id) {
+                eprintln!("Warning: {}", e);
+            }
+        }
+        if preset_manager.get_active_presets().is_empty() {
+            preset_manager.select_initial_preset(&env_presets_config);
+        }
+        let from_cache = cached.is_some();
+        let catalog_status = CatalogStatus {
+            source: if from_cache { CatalogSource::Cache } else { CatalogSource::Disk },
+            stale: from_cache,
+            refreshing: false,
+            cache_saved_at: cached.as_ref().map(|cache| cache.saved_at),
+            error: None,
+        };
+        match cached {
+            // Cached nodes only apply when they were loaded from the VARO_PATH the stack resolves to
+            Some(mut cache) => {
+                let varo_path = node_manager.resolve_varo_path(preset_manager.get_active_presets());
+                if let Some(varo_path) = varo_path.filter(|path| Some(path) == cache.varo_path.as_ref()) {
+                    let icons = cache.take_icons();
+                    node_manager.use_cached_nodes(varo_path, cache.nodes, icons);
+                }
+            }
+            None => {
+                let load_result = node_manager.refresh_with_presets(preset_manager.get_active_presets());
+                if let Err(e) = load_result {
+                    eprintln!("Warning: Failed to load nodes: {}", e);
+                }
+            }
         }
 
-        Self {
+        let process_registry = ProcessRegistry::new();
+        process_registry.set_log_settings(LaunchLogSettings::from_config(&config_manager.get_section("logs")));
+
+        let core = Self {
             config_manager: Arc::new(RwLock::new(config_manager)),
             preset_manager: Arc::new(RwLock::new(preset_manager)),
             node_manager: Arc::new(RwLock::new(node_manager)),
+            process_registry,
             system_info,
+            watcher: Mutex::new(None),
+            catalog_cache,
+            catalog_status: Mutex::new(catalog_status),
+            deferred_changes: Mutex::new(FileChanges::default()),
+            preset_scan: Mutex::new(()),
+            node_scan: Mutex::new(()),
+        };
+        // A catalog that came from the cache is only saved again once a scan has replaced it, so
+        // the cache keeps the time of the scan it holds and an unused one is not overwritten
+        if !from_cache {
+            core.sync_save_catalog_cache();
         }
+        core
     }
 
     // Async methods for CLI/advanced usage
     pub async fn refresh_env_preset(&self, preset_id: &str) -> VaroResult<()> {
+        let mut node_manager = self.node_manager.write().await;
         let mut preset_manager = self.preset_manager.write().await;
         preset_manager.select_preset(preset_id)?;
         
-        if let Some(preset) = preset_manager.get_selected_preset() {
-            let mut node_manager = self.node_manager.write().await;
-            node_manager.refresh_with_preset(preset)?;
-        }
+        node_manager.refresh_with_presets(preset_manager.get_active_presets())?;
         
         Ok(())
     }
 
     pub async fn execute_node(&self, node_id: &str) -> VaroResult<()> {
-        let node_manager = self.node_manager.read().await;
-        let preset_manager = self.preset_manager.read().await;
-        let selected_preset = preset_manager.get_selected_preset();
-        
-        node_manager.execute_node_with_env_expansion(node_id, selected_preset)
+        let launch = {
+            let node_manager = self.node_manager.read().await;
+            let preset_manager = self.preset_manager.read().await;
+            node_manager.prepare_launch(node_id, preset_manager.get_active_presets())?
+        };
+        launch.run(&self.process_registry)
     }
 
     pub async fn get_all_presets(&self) -> Vec<EnvPreset> {
@@ -93,27 +193,155 @@ impl VaroCore {
         self.preset_manager.blocking_read().get_selected_preset().cloned()
     }
 
+    pub fn sync_get_active_presets(&self) -> Vec<EnvPreset> {
+        self.preset_manager.blocking_read().get_active_presets().to_vec()
+    }
+
     pub fn sync_select_preset(&self, preset_id: &str) -> VaroResult<()> {
-        println!("[VaroCore] Selecting preset: {}", preset_id);
-        
-        // Select the preset
+        varo_log!("[VaroCore] Selecting preset: {}", preset_id);
+        self.sync_update_active_presets(|presets| presets.select_preset(preset_id))
+    }
+
+    /// Activate a preset on top of the current stack
+    pub fn sync_push_preset(&self, preset_id: &str) -> VaroResult<()> {
+        varo_log!("[VaroCore] Pushing preset: {}", preset_id);
+        self.sync_update_active_presets(|presets| presets.push_preset(preset_id))
+    }
+
+    /// Deactivate the top-most preset, returning it
+    pub fn sync_pop_preset(&self) -> VaroResult<Option<EnvPreset>> {
+        varo_log!("[VaroCore] Popping top-most preset");
+        self.sync_update_active_presets(|presets| Ok(presets.pop_preset()))
+    }
+
+    /// Reorder the active presets, bottom layer first
+    pub fn sync_reorder_presets(&self, preset_ids: &[String]) -> VaroResult<()> {
+        varo_log!("[VaroCore] Reordering presets: {:?}", preset_ids);
+        self.sync_update_active_presets(|presets| presets.reorder_presets(preset_ids))
+    }
+
+    /// Change the active preset stack, reload nodes through it and remember it for the next launch
+    fn sync_update_active_presets<T, F>(&self, update: F) -> VaroResult<T>
+    where
+        F: FnOnce(&mut PresetManager) -> VaroResult<T>,
+    {
         let mut preset_manager = self.preset_manager.blocking_write();
-        preset_manager.select_preset(preset_id)?;
-        
-        // Get the selected preset for node refresh
-        let selected_preset = preset_manager.get_selected_preset().cloned();
+        let result = update(&mut preset_manager)?;
+        let active_presets = preset_manager.get_active_presets().to_vec();
         drop(preset_manager); // Release preset lock
         
-        // Reload nodes with the new preset (which may have changed VARO_PATH)
-        let mut node_manager = self.node_manager.blocking_write();
-        if let Some(preset) = selected_preset {
-            node_manager.refresh_with_preset(&preset)?;
-        } else {
-            node_manager.load_nodes_from_varo_path()?;
-        }
+        // Reload nodes with the new stack (the top-most VARO_PATH may have changed)
+        self.sync_refresh_nodes(&active_presets);
         
-        println!("[VaroCore] Preset selection and node refresh complete");
-        Ok(())
+        Self::save_active_presets(&active_presets);
+        self.sync_update_watch_targets();
+        self.sync_save_catalog_cache();
+        varo_log!("[VaroCore] Active presets updated and nodes refreshed");
+        Ok(result)
+    }
+
+    /// Reload nodes through `presets`. Node files are read without holding the node lock, so
+    /// commands keep seeing the current nodes until the scanned ones are swapped in.
+    fn sync_refresh_nodes(&self, presets: &[EnvPreset]) {
+        let _scan = self.lock_node_scan();
+        let scan = self.node_manager.blocking_read().prepare_scan(presets);
+        let scanned = scan.run();
+        self.node_manager.blocking_write().install_scan(scanned, presets);
+    }
+
+    /// Taken before [`Self::lock_node_scan`] when both are needed
+    fn lock_preset_scan(&self) -> MutexGuard<'_, ()> {
+        self.preset_scan.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_node_scan(&self) -> MutexGuard<'_, ()> {
+        self.node_scan.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Remember the stack so it is restored on the next launch. `last_selected_id` keeps
+    /// the top-most preset for configs that only know about a single selection.
+    fn save_active_presets(active_presets: &[EnvPreset]) {
+        let active_ids: Vec<Value> = active_presets.iter().map(|p| Value::String(p.id.clone())).collect();
+        let selected_id = active_presets.last().map_or(Value::Null, |p| Value::String(p.id.clone()));
+        for (key, value) in [("last_active_ids", Value::Array(active_ids)), ("last_selected_id", selected_id)] {
+            if let Err(e) = config::save_user_config_value(&["env_presets", key], value) {
+                eprintln!("[VaroCore] Warning: Failed to save {}: {}", key, e);
+            }
+        }
+    }
+
+    /// Write a new JSON preset and load it. See [`PresetManager::create_preset`].
+    pub fn sync_create_preset(&self, directory: Option<&str>, preset: &Value) -> VaroResult<EnvPreset> {
+        let id = preset.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        varo_log!("[VaroCore] Creating preset: {}", id);
+        self.sync_edit_presets(|presets| presets.create_preset(directory, preset))?;
+        self.sync_get_edited_preset(&id)
+    }
+
+    /// Change fields of a JSON preset and reload it. See [`PresetManager::update_preset`].
+    pub fn sync_update_preset(&self, preset_id: &str, fields: &Value) -> VaroResult<EnvPreset> {
+        varo_log!("[VaroCore] Updating preset: {}", preset_id);
+        self.sync_edit_presets(|presets| presets.update_preset(preset_id, fields))?;
+        self.sync_get_edited_preset(preset_id)
+    }
+
+    /// Copy a preset under a new id and load the copy. See [`PresetManager::duplicate_preset`].
+    pub fn sync_duplicate_preset(&self, preset_id: &str, new_id: &str, name: Option<&str>, directory: Option<&str>) -> VaroResult<EnvPreset> {
+        varo_log!("[VaroCore] Duplicating preset {} as {}", preset_id, new_id);
+        self.sync_edit_presets(|presets| presets.duplicate_preset(preset_id, new_id, name, directory))?;
+        self.sync_get_edited_preset(new_id)
+    }
+
+    /// Delete a preset file and reload the presets. See [`PresetManager::delete_preset`].
+    pub fn sync_delete_preset(&self, preset_id: &str) -> VaroResult<()> {
+        varo_log!("[VaroCore] Deleting preset: {}", preset_id);
+        self.sync_edit_presets(|presets| presets.delete_preset(preset_id)).map(|_| ())
+    }
+
+    /// Run a preset file edit, then reload presets and nodes so the change applies without a restart.
+    /// The active stack is saved again when the edit removed one of its presets.
+    fn sync_edit_presets<T, F>(&self, edit: F) -> VaroResult<T>
+    where
+        F: FnOnce(&PresetManager) -> VaroResult<T>,
+    {
+        let env_presets_config = self.config_manager.blocking_read().get_section("env_presets");
+
+        let preset_scan = self.lock_preset_scan();
+        let preset_manager = self.preset_manager.blocking_read();
+        let result = edit(&preset_manager)?;
+        let active_before = preset_manager.get_active_presets().len();
+        drop(preset_manager); // Release preset lock while providers run
+
+        let scan = PresetManager::scan(&env_presets_config);
+        let mut preset_manager = self.preset_manager.blocking_write();
+        preset_manager.install_scan(scan);
+        let active_presets = preset_manager.get_active_presets().to_vec();
+        drop(preset_manager); // Release preset lock
+        drop(preset_scan);
+
+        // Edited presets may be active, so nodes are reloaded with their new values
+        self.sync_refresh_nodes(&active_presets);
+
+        if active_presets.len() != active_before {
+            Self::save_active_presets(&active_presets);
+        }
+        self.sync_update_watch_targets();
+        self.sync_save_catalog_cache();
+
+        varo_log!("[VaroCore] Presets edited and reloaded");
+        Ok(result)
+    }
+
+    /// The freshly loaded version of an edited preset. The file was written, but the preset can
+    /// still fail to load as a whole (e.g. when its provider fails); the diagnostics say why.
+    fn sync_get_edited_preset(&self, preset_id: &str) -> VaroResult<EnvPreset> {
+        self.preset_manager.blocking_read()
+            .get_preset(preset_id)
+            .cloned()
+            .ok_or_else(|| VaroError::env_preset(format!(
+                "Preset '{}' was saved but could not be loaded; see the diagnostics for details",
+                preset_id
+            )))
     }
 
     pub fn sync_get_config(&self) -> Value {
@@ -121,30 +349,326 @@ impl VaroCore {
     }
 
     pub fn sync_reload_config(&self) -> VaroResult<()> {
-        println!("[VaroCore] Starting config reload...");
-        
-        let mut config_manager = self.config_manager.blocking_write();
-        config_manager.reload()?;
-        println!("[VaroCore] Config reloaded successfully");
-        
-        // Also reload presets with new config
-        let env_presets_config = config_manager.get_section("env_presets");
-        drop(config_manager); // Release config lock
+        varo_log!("[VaroCore] Starting config reload...");
         
+        self.config_manager.blocking_write().reload()?;
+        varo_log!("[VaroCore] Config reloaded successfully");
+
+        // An explicit reload reads every node file again, e.g. to pick up icons that appeared since
+        self.node_manager.blocking_write().clear_cache();
+
+        if self.is_catalog_stale() {
+            // Still showing the cached catalog: scan like the startup refresh does, so the cache is
+            // kept when the scan fails and `catalog_cache.keep_on_scan_failure` is set
+            self.sync_apply_settings();
+            let load = self.catalog_loader();
+            self.sync_install_catalog(load())?;
+        } else {
+            self.sync_apply_config()?;
+        }
+        varo_log!("[VaroCore] Complete config reload finished successfully");
+        Ok(())
+    }
+
+    /// Apply the `logs`, `access` and `nodes` config sections
+    fn sync_apply_settings(&self) {
+        let config_manager = self.config_manager.blocking_read();
+        self.process_registry.set_log_settings(LaunchLogSettings::from_config(&config_manager.get_section("logs")));
+        let mut node_manager = self.node_manager.blocking_write();
+        node_manager.set_access_config(&config_manager.get_section("access"));
+        node_manager.set_nodes_config(&config_manager.get_section("nodes"));
+    }
+
+    /// Reload presets and nodes with the current configuration
+    fn sync_apply_config(&self) -> VaroResult<()> {
+        self.sync_apply_settings();
+        let env_presets_config = self.config_manager.blocking_read().get_section("env_presets");
+
+        // Providers run before the preset lock is taken, so commands keep seeing the current presets
+        let preset_scan = self.lock_preset_scan();
+        let scan = PresetManager::scan(&env_presets_config);
         let mut preset_manager = self.preset_manager.blocking_write();
-        preset_manager.reload(&env_presets_config)?;
-        println!("[VaroCore] Presets reloaded successfully");
+        preset_manager.install_scan(scan);
+        drop(preset_scan);
+        if preset_manager.get_active_presets().is_empty() {
+            preset_manager.select_initial_preset(&env_presets_config);
+        }
+        let active_presets = preset_manager.get_active_presets().to_vec();
+        varo_log!("[VaroCore] Presets reloaded successfully");
         drop(preset_manager); // Release preset lock
         
-        // Also reload nodes from disk
-        let mut node_manager = self.node_manager.blocking_write();
-        node_manager.load_nodes_from_varo_path()?;
-        println!("[VaroCore] Nodes reloaded successfully");
-        
-        println!("[VaroCore] Complete config reload finished successfully");
+        // Also reload nodes from disk through the active presets
+        self.sync_refresh_nodes(&active_presets);
+        varo_log!("[VaroCore] Nodes reloaded successfully");
+
+        self.sync_update_watch_targets();
+        self.sync_save_catalog_cache();
         Ok(())
     }
 
+    /// Watch node, icon, preset and config folders, calling `on_change` with every debounced batch
+    /// of changes. The callback is expected to pass them to [`VaroCore::sync_apply_file_changes`].
+    /// Does nothing when `watch.enabled` is false; `watch` settings apply on the next start.
+    pub fn start_watching<F>(&self, on_change: F)
+    where
+        F: FnMut(FileChanges) + Send + 'static,
+    {
+        let settings = WatchSettings::from_config(&self.config_manager.blocking_read().get_section("watch"));
+        if !settings.enabled {
+            varo_log!("[VaroCore] File watching disabled");
+            return;
+        }
+        let mut watcher = FileWatcher::start(settings, on_change);
+        watcher.set_targets(&self.watch_targets());
+        if let Ok(mut slot) = self.watcher.lock() {
+            *slot = Some(watcher);
+        }
+    }
+
+    /// Folders whose files make up the catalog: the folders of the config files, the preset
+    /// directories, `VARO_PATH/nodes` and `VARO_PATH/icons` (with subfolders, as icons may be nested)
+    fn watch_targets(&self) -> Vec<WatchTarget> {
+        let config_files = self.config_manager.blocking_read().get_config_file_paths();
+        let preset_dirs = self.preset_manager.blocking_read().get_directories();
+        let node_manager = self.node_manager.blocking_read();
+
+        let mut targets: Vec<WatchTarget> = Vec::new();
+        let flat = config_files.iter().filter_map(|file| file.parent().map(PathBuf::from))
+            .chain(preset_dirs)
+            .chain(node_manager.get_nodes_dir());
+        for path in flat {
+            targets.push(WatchTarget { path, recursive: false });
+        }
+        if let Some(path) = node_manager.get_icons_dir() {
+            targets.push(WatchTarget { path, recursive: true });
+        }
+
+        let mut unique: Vec<WatchTarget> = Vec::new();
+        for target in targets {
+            if !unique.contains(&target) {
+                unique.push(target);
+            }
+        }
+        unique
+    }
+
+    /// Point the watcher at the current folders, which move when VARO_PATH or the config changes
+    fn sync_update_watch_targets(&self) {
+        let mut watcher = match self.watcher.lock() {
+            Ok(watcher) => watcher,
+            Err(_) => return,
+        };
+        if let Some(watcher) = watcher.as_mut() {
+            watcher.set_targets(&self.watch_targets());
+        }
+    }
+
+    /// Apply files changed on disk. A changed config file reloads everything like
+    /// [`VaroCore::sync_reload_config`]; otherwise only the changed preset and node files (and
+    /// nodes using a changed icon) are reloaded. Returns what the frontend needs to update.
+    pub fn sync_apply_file_changes(&self, changes: &FileChanges) -> VaroResult<CatalogDelta> {
+        // The cached catalog has no files to update; the pending scan reads them anyway, and what
+        // changes meanwhile is applied once the scanned catalog is installed. Staleness is checked
+        // under the deferred lock, so changes cannot slip in while the scanned catalog is installed.
+        if let Ok(mut deferred) = self.deferred_changes.lock() {
+            if self.is_catalog_stale() {
+                deferred.paths.extend(changes.paths.iter().cloned());
+                deferred.rescan |= changes.rescan;
+                return Ok(CatalogDelta::default());
+            }
+        }
+
+        let before = self.sync_catalog_snapshot();
+        let paths: Vec<PathBuf> = changes.paths.iter().cloned().collect();
+
+        let mut config_manager = self.config_manager.blocking_write();
+        let config_files = config_manager.get_config_file_paths();
+        let config_touched = paths.iter().any(|path| config_files.contains(path));
+        let config_changed = if config_touched || changes.rescan {
+            config_manager.reload_if_changed()?
+        } else {
+            false
+        };
+        drop(config_manager); // Release config lock
+
+        if config_changed || changes.rescan {
+            varo_log!("[VaroCore] Config changed on disk, reloading everything");
+            self.sync_apply_config()?;
+        } else {
+            let preset_scan = self.lock_preset_scan();
+            let directories = self.preset_manager.blocking_read().get_directories();
+            let changed_files = PresetManager::load_changed_files(&directories, &paths);
+            let mut preset_manager = self.preset_manager.blocking_write();
+            let active_before = preset_manager.get_active_presets().to_vec();
+            preset_manager.install_changed_files(changed_files);
+            let active_presets = preset_manager.get_active_presets().to_vec();
+            drop(preset_manager); // Release preset lock
+            drop(preset_scan);
+
+            if Self::same_presets(&active_before, &active_presets) {
+                let _scan = self.lock_node_scan();
+                self.node_manager.blocking_write().apply_file_changes(&paths, &active_presets);
+            } else {
+                // The active environment changed and VARO_PATH may have moved, so all nodes are reloaded
+                self.sync_refresh_nodes(&active_presets);
+                if active_presets.len() != active_before.len() {
+                    Self::save_active_presets(&active_presets);
+                }
+                self.sync_update_watch_targets();
+            }
+        }
+
+        let delta = before.delta(&self.sync_catalog_snapshot(), config_changed);
+        if !delta.is_empty() {
+            self.sync_save_catalog_cache();
+        }
+        Ok(delta)
+    }
+
+    /// Whether the catalog shown is fresh or still the one saved by an earlier session
+    pub fn sync_get_catalog_status(&self) -> CatalogStatus {
+        self.catalog_status.lock()
+            .map(|status| status.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+
+    fn update_catalog_status<F: FnOnce(&mut CatalogStatus)>(&self, update: F) {
+        match self.catalog_status.lock() {
+            Ok(mut status) => update(&mut status),
+            Err(e) => update(&mut e.into_inner()),
+        }
+    }
+
+    fn is_catalog_stale(&self) -> bool {
+        self.sync_get_catalog_status().stale
+    }
+
+    /// When the catalog was started from the cache, scan presets and nodes on a background thread.
+    /// `on_loaded` receives the result, which is meant for [`VaroCore::sync_install_catalog`].
+    /// Returns false when the catalog is already fresh.
+    pub fn start_catalog_refresh<F>(&self, on_loaded: F) -> bool
+    where
+        F: FnOnce(LoadedCatalog) + Send + 'static,
+    {
+        if !self.is_catalog_stale() {
+            return false;
+        }
+        varo_log!("[VaroCore] Refreshing the cached catalog in the background");
+        let load = self.catalog_loader();
+        std::thread::spawn(move || on_loaded(load()));
+        true
+    }
+
+    /// Prepare a scan that reads presets and nodes without holding any lock
+    fn catalog_loader(&self) -> impl FnOnce() -> LoadedCatalog + Send + 'static {
+        let env_presets_config = self.config_manager.blocking_read().get_section("env_presets");
+        let active_ids: Vec<String> = self.preset_manager.blocking_read()
+            .get_active_presets()
+            .iter()
+            .map(|p| p.id.clone())
+            .collect();
+        let node_manager = self.node_manager.blocking_read().detached();
+        self.update_catalog_status(|status| status.refreshing = true);
+        move || LoadedCatalog::load(&env_presets_config, &active_ids, node_manager)
+    }
+
+    /// Replace the cached catalog with a scanned one. When the scan could not read presets or
+    /// nodes and `catalog_cache.keep_on_scan_failure` is set, the cached catalog stays and remains
+    /// stale. Returns what changed; file changes seen meanwhile are applied too.
+    pub fn sync_install_catalog(&self, loaded: LoadedCatalog) -> VaroResult<CatalogDelta> {
+        let error = loaded.scan_error();
+        let keep_on_failure = self.catalog_cache.as_ref().is_some_and(|settings| settings.keep_on_scan_failure);
+        if error.is_some() && keep_on_failure && self.is_catalog_stale() {
+            varo_log!("[VaroCore] Keeping the cached catalog, the scan failed: {}", error.as_deref().unwrap_or_default());
+            self.update_catalog_status(|status| {
+                status.refreshing = false;
+                status.error = error;
+            });
+            return Ok(CatalogDelta::default());
+        }
+
+        let before = self.sync_catalog_snapshot();
+        let active_presets = loaded.preset_manager.get_active_presets().to_vec();
+        let active_ids: Vec<&str> = active_presets.iter().map(|p| p.id.as_str()).collect();
+        if active_ids != before.active_preset_ids {
+            Self::save_active_presets(&active_presets);
+        }
+        let preset_scan = self.lock_preset_scan();
+        let node_scan = self.lock_node_scan();
+        *self.preset_manager.blocking_write() = loaded.preset_manager;
+        *self.node_manager.blocking_write() = loaded.node_manager;
+        drop(node_scan);
+        drop(preset_scan);
+        self.update_catalog_status(|status| {
+            status.source = CatalogSource::Disk;
+            status.stale = false;
+            status.refreshing = false;
+            status.error = error;
+        });
+        varo_log!("[VaroCore] Installed the scanned catalog");
+
+        let deferred = self.deferred_changes.lock()
+            .map(|mut deferred| std::mem::take(&mut *deferred))
+            .unwrap_or_default();
+        if deferred.rescan || !deferred.paths.is_empty() {
+            self.sync_apply_file_changes(&deferred)?;
+        }
+        self.sync_update_watch_targets();
+        self.sync_save_catalog_cache();
+        Ok(before.delta(&self.sync_catalog_snapshot(), false))
+    }
+
+    /// Save the catalog for the next start. Nothing is saved while the cached catalog is still
+    /// shown, or when the last scan could not read everything, so a good cache is never replaced
+    /// by the result of an outage.
+    fn sync_save_catalog_cache(&self) {
+        let path = match self.catalog_cache.as_ref().and_then(|settings| settings.path.clone()) {
+            Some(path) => path,
+            None => return,
+        };
+        if self.is_catalog_stale() {
+            return;
+        }
+
+        let node_manager = self.node_manager.blocking_read();
+        let preset_manager = self.preset_manager.blocking_read();
+        if let Some(error) = scan_error(&preset_manager, &node_manager) {
+            varo_log!("[VaroCore] Not saving the catalog cache, the scan failed: {}", error);
+            return;
+        }
+        let cache = CatalogCache::new(
+            node_manager.get_varo_path().map(String::from),
+            preset_manager.get_all_presets(),
+            node_manager.get_loaded_nodes().into_iter().cloned().collect(),
+            node_manager.get_loaded_icons(),
+        );
+        drop(preset_manager);
+        drop(node_manager);
+
+        match cache.save(&path) {
+            Ok(()) => self.update_catalog_status(|status| status.cache_saved_at = Some(cache.saved_at)),
+            Err(e) => eprintln!("Warning: Failed to save the catalog cache: {}", e),
+        }
+    }
+
+    fn same_presets(a: &[EnvPreset], b: &[EnvPreset]) -> bool {
+        serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+    }
+
+    fn sync_catalog_snapshot(&self) -> CatalogSnapshot {
+        // Read the diagnostics before holding the preset lock: reading it a second time while a
+        // writer waits would deadlock, as waiting writers go before new readers
+        let nodes = self.sync_get_all_nodes();
+        let diagnostics = self.sync_get_diagnostics();
+        let preset_manager = self.preset_manager.blocking_read();
+        CatalogSnapshot {
+            nodes,
+            presets: preset_manager.get_all_presets(),
+            active_preset_ids: preset_manager.get_active_presets().iter().map(|p| p.id.clone()).collect(),
+            diagnostics,
+        }
+    }
+
     pub fn get_username(&self) -> &str {
         self.system_info.get_username()
     }
@@ -160,25 +684,106 @@ impl VaroCore {
             .collect()
     }
 
+    /// Launch a node. Access, the environment and the first command are checked here, so their
+    /// errors reach the caller; the commands are then started and waited for on another thread.
     pub fn sync_execute_node(&self, node_id: &str) -> VaroResult<()> {
-        let node_manager = self.node_manager.clone();
-        let preset_manager = self.preset_manager.clone();
-        let node_id = node_id.to_string();
-        
-        // Execute in a separate thread to avoid blocking the UI
+        let launch = {
+            let node_manager = self.node_manager.blocking_read();
+            let preset_manager = self.preset_manager.blocking_read();
+            node_manager.prepare_launch(node_id, preset_manager.get_active_presets())?
+        };
+
+        // Run in a separate thread to avoid blocking the UI while commands with `wait` run
+        let registry = self.process_registry.clone();
         std::thread::spawn(move || {
-            let node_manager = node_manager.blocking_read();
-            let preset_manager = preset_manager.blocking_read();
-            let selected_preset = preset_manager.get_selected_preset();
-            
-            if let Err(e) = node_manager.execute_node_with_env_expansion(&node_id, selected_preset) {
+            let node_id = launch.node_id().to_string();
+            if let Err(e) = launch.run(&registry) {
                 eprintln!("[Node Execution] Error executing node {}: {}", node_id, e);
             }
         });
-        
+
         Ok(())
     }
 
+    /// Problems found while loading presets and nodes, presets first
+    pub fn sync_get_diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.preset_manager.blocking_read().get_diagnostics().to_vec();
+        diagnostics.extend_from_slice(self.node_manager.blocking_read().get_diagnostics());
+        diagnostics
+    }
+
+    /// The icon with `key`, scaled down to fit `size` pixels when given, for the `varo-icon` protocol
+    pub fn sync_get_icon(&self, key: &str, size: Option<u32>) -> Option<Arc<Icon>> {
+        self.node_manager.blocking_read().get_icon(key, size)
+    }
+
+    /// Timing and cache statistics of the last node scan
+    pub fn sync_get_scan_stats(&self) -> ScanStats {
+        self.node_manager.blocking_read().get_scan_stats().clone()
+    }
+
+    pub fn sync_get_hidden_nodes(&self) -> Vec<NodeAccessReport> {
+        self.node_manager.blocking_read().get_hidden_nodes()
+    }
+
+    pub fn sync_explain_node_access(&self, node_id: &str) -> VaroResult<NodeAccessReport> {
+        self.node_manager.blocking_read().explain_node_access(node_id)
+    }
+
+    pub fn sync_list_launches(&self, include_finished: bool) -> Vec<LaunchInfo> {
+        self.process_registry.list(include_finished)
+    }
+
+    pub fn sync_kill_launch(&self, launch_id: u64) -> VaroResult<()> {
+        self.process_registry.kill(launch_id).map_err(VaroError::execution)
+    }
+
+    pub fn sync_read_launch_log(&self, launch_id: u64, offset: u64, max_bytes: Option<usize>) -> VaroResult<LaunchLogChunk> {
+        self.process_registry.read_log(launch_id, offset, max_bytes).map_err(VaroError::execution)
+    }
+
+    /// Register a callback for launch start and exit events
+    pub fn set_launch_listener<F: Fn(&LaunchEvent) + Send + Sync + 'static>(&self, listener: F) {
+        self.process_registry.set_listener(listener);
+    }
+
+    /// Resolve what launching a node with the active presets would run, without starting it
+    pub fn sync_plan_node_launch(&self, node_id: &str) -> VaroResult<LaunchPlan> {
+        let node_manager = self.node_manager.blocking_read();
+        let preset_manager = self.preset_manager.blocking_read();
+        node_manager.plan_node_launch(node_id, preset_manager.get_active_presets())
+    }
+
+    /// Execute a node on the calling thread, returning once all of its commands have been started
+    /// (and commands with `wait` have finished). No lock is held while the commands run.
+    pub fn sync_execute_node_and_wait(&self, node_id: &str) -> VaroResult<()> {
+        let launch = {
+            let node_manager = self.node_manager.blocking_read();
+            let preset_manager = self.preset_manager.blocking_read();
+            node_manager.prepare_launch(node_id, preset_manager.get_active_presets())?
+        };
+        launch.run(&self.process_registry)
+    }
+
+    /// Render the environment of the active presets, and optionally a node, as an activation script
+    pub fn sync_export_env(&self, node_id: Option<&str>, format: ExportFormat) -> VaroResult<String> {
+        let node_manager = self.node_manager.blocking_read();
+        let preset_manager = self.preset_manager.blocking_read();
+        let presets = preset_manager.get_active_presets();
+        let vars = node_manager.export_env(node_id, presets)?;
+
+        let preset_ids: Vec<&str> = presets.iter().map(|p| p.id.as_str()).collect();
+        let mut header = vec![
+            "Generated by Varo".to_string(),
+            format!("Presets: {}", if preset_ids.is_empty() { "(none)".to_string() } else { preset_ids.join(" > ") }),
+        ];
+        if let Some(node) = node_id.and_then(|id| node_manager.get_node(id)) {
+            header.push(format!("Node: {} ({})", node.name, node.id));
+        }
+
+        render_env_script(&vars, format, &header).map_err(VaroError::env_preset)
+    }
+
     pub fn sync_show_node_in_folder(&self, node_id: &str) -> VaroResult<()> {
         self.node_manager.blocking_read().show_node_in_folder(node_id)
     }
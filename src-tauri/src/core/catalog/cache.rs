@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::engine::general_purpose;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::models::entities::{EnvPreset, VaroNode};
+use crate::utils::config::get_user_varo_dir;
+use crate::utils::files::write_file_atomic;
+use crate::utils::icon::Icon;
+use crate::utils::log::varo_log;
+
+/// Bumped whenever the layout of the cache file changes; files with another version are ignored
+const CACHE_VERSION: u32 = 2;
+
+/// Settings from the `catalog_cache` config section
+#[derive(Debug, Clone)]
+pub struct CatalogCacheSettings {
+    pub enabled: bool,
+    /// Keep showing the cached catalog when the scan after startup cannot read presets or nodes
+    pub keep_on_scan_failure: bool,
+    pub path: Option<PathBuf>,
+}
+
+impl Default for CatalogCacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            keep_on_scan_failure: false,
+            path: get_user_varo_dir().map(|dir| dir.join("cache").join("catalog.json")),
+        }
+    }
+}
+
+impl CatalogCacheSettings {
+    pub fn from_config(cache_config: &Value) -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: cache_config.get("enabled").and_then(|v| v.as_bool()).unwrap_or(defaults.enabled),
+            keep_on_scan_failure: cache_config.get("keep_on_scan_failure")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(defaults.keep_on_scan_failure),
+            path: defaults.path,
+        }
+    }
+}
+
+/// The last catalog read from disk, saved so the next start can show it before scanning
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogCache {
+    pub version: u32,
+    pub app_version: String,
+    pub saved_at: u64, // ms since the Unix epoch
+    pub varo_path: Option<String>, // the nodes were loaded from `{varo_path}/nodes`
+    pub presets: Vec<EnvPreset>,
+    pub nodes: Vec<VaroNode>, // `icon` holds a key into `icons`
+    /// Icons by content hash, so an icon shared by several nodes is stored once
+    pub icons: BTreeMap<String, CachedIcon>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedIcon {
+    pub mime: String,
+    pub data: String, // base64
+}
+
+impl CatalogCache {
+    pub fn new(varo_path: Option<String>, presets: Vec<EnvPreset>, nodes: Vec<VaroNode>, icons: Vec<Arc<Icon>>) -> Self {
+        let icons = icons.into_iter()
+            .map(|icon| (icon.key.clone(), CachedIcon {
+                mime: icon.mime.clone(),
+                data: general_purpose::STANDARD.encode(&icon.bytes),
+            }))
+            .collect();
+
+        Self {
+            version: CACHE_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            saved_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+            varo_path,
+            presets,
+            nodes,
+            icons,
+        }
+    }
+
+    /// Read the cache at `path`. Returns `None` when there is none, or when it was written by
+    /// another version of Varo or cannot be parsed.
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let cache: Self = match serde_json::from_str(&content) {
+            Ok(cache) => cache,
+            Err(e) => {
+                eprintln!("Warning: Ignoring unreadable catalog cache '{}': {}", path.display(), e);
+                return None;
+            }
+        };
+        if cache.version != CACHE_VERSION || cache.app_version != env!("CARGO_PKG_VERSION") {
+            varo_log!("[Catalog Cache] Ignoring cache from Varo {} (format {})", cache.app_version, cache.version);
+            return None;
+        }
+
+        varo_log!("[Catalog Cache] Loaded {} presets and {} nodes from {}", cache.presets.len(), cache.nodes.len(), path.display());
+        Some(cache)
+    }
+
+    /// The cached icons, to be added to the node manager along with the nodes
+    pub fn take_icons(&mut self) -> Vec<Icon> {
+        std::mem::take(&mut self.icons)
+            .into_values()
+            .filter_map(|icon| {
+                let bytes = general_purpose::STANDARD.decode(icon.data).ok()?;
+                Some(Icon::new(icon.mime, bytes))
+            })
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+        }
+        let content = serde_json::to_vec(self).map_err(|e| format!("Failed to serialize catalog cache: {}", e))?;
+        write_file_atomic(path, &content)?;
+        varo_log!("[Catalog Cache] Saved {} presets and {} nodes to {}", self.presets.len(), self.nodes.len(), path.display());
+        Ok(())
+    }
+}
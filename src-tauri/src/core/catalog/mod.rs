@@ -0,0 +1,5 @@
+pub mod cache;
+pub mod refresh;
+
+pub use cache::{CatalogCache, CatalogCacheSettings};
+pub use refresh::LoadedCatalog;
\ No newline at end of file
@@ -0,0 +1,51 @@
+use serde_json::Value;
+use crate::core::env_presets::PresetManager;
+use crate::core::nodes::NodeManager;
+use crate::utils::log::varo_log;
+
+/// Presets and nodes read from disk without holding the live managers' locks, ready to replace
+/// a catalog that was started from the cache
+#[derive(Debug)]
+pub struct LoadedCatalog {
+    pub preset_manager: PresetManager,
+    pub node_manager: NodeManager,
+}
+
+impl LoadedCatalog {
+    /// Scan the preset directories and nodes folder. `active_ids` is the stack shown meanwhile and
+    /// is kept where its presets still exist; `node_manager` is a [`NodeManager::detached`] copy.
+    pub fn load(env_presets_config: &Value, active_ids: &[String], mut node_manager: NodeManager) -> Self {
+        let mut preset_manager = match PresetManager::new(env_presets_config) {
+            Ok(manager) => manager,
+            Err(e) => {
+                eprintln!("Warning: Failed to load presets: {}", e);
+                PresetManager::empty()
+            }
+        };
+        for id in active_ids {
+            if let Err(e) = preset_manager.push_preset(id) {
+                varo_log!("[Catalog] Warning: {}", e);
+            }
+        }
+        if preset_manager.get_active_presets().is_empty() {
+            preset_manager.select_initial_preset(env_presets_config);
+        }
+
+        if let Err(e) = node_manager.refresh_with_presets(preset_manager.get_active_presets()) {
+            eprintln!("Warning: Failed to load nodes: {}", e);
+        }
+        Self { preset_manager, node_manager }
+    }
+
+    /// Why the scan could not read the catalog, e.g. an unreachable file server
+    pub fn scan_error(&self) -> Option<String> {
+        scan_error(&self.preset_manager, &self.node_manager)
+    }
+}
+
+/// Why the last scan of these managers could not read preset directories or the nodes folder
+pub fn scan_error(preset_manager: &PresetManager, node_manager: &NodeManager) -> Option<String> {
+    let mut errors = preset_manager.get_directory_errors();
+    errors.extend(node_manager.get_scan_stats().error.clone());
+    (!errors.is_empty()).then(|| errors.join("; "))
+}
\ No newline at end of file
@@ -1,31 +1,53 @@
+use std::path::{Path, PathBuf};
 use serde_json::{Value, json};
 use crate::utils::config;
 use crate::utils::env::resolve_env_vars_recursive;
 use crate::models::errors::{VaroError, VaroResult};
+use crate::utils::log::varo_log;
+
+/// `env_presets` keys Varo writes to the user config to restore the active presets on the next start
+const SESSION_KEYS: [&str; 2] = ["last_selected_id", "last_active_ids"];
 
 #[derive(Debug, Clone)]
 pub struct ConfigManager {
     config: Value,
+    cli_config_path: Option<PathBuf>,
 }
 
 impl ConfigManager {
     /// Load and initialize the configuration with variable resolution
     pub fn load() -> VaroResult<Self> {
-        let mut config = Self::load_merged_config();
+        Self::load_with_cli_config(None)
+    }
+
+    /// Load the configuration with an additional command-line (`--config`) layer on top
+    pub fn load_with_cli_config(cli_config_path: Option<PathBuf>) -> VaroResult<Self> {
+        let mut config = Self::load_merged_config(cli_config_path.as_deref())?;
         
         // Resolve environment variables recursively throughout the config
         resolve_env_vars_recursive(&mut config);
         
-        Ok(Self { config })
+        Ok(Self { config, cli_config_path })
     }
 
     /// Create an empty configuration manager
     pub fn empty() -> Self {
         Self {
             config: serde_json::Value::Object(serde_json::Map::new()),
+            cli_config_path: None,
         }
     }
 
+    /// Get the path passed with `--config`, if any
+    pub fn get_cli_config_path(&self) -> Option<&Path> {
+        self.cli_config_path.as_deref()
+    }
+
+    /// Get the config files this configuration is merged from
+    pub fn get_config_file_paths(&self) -> Vec<PathBuf> {
+        config::get_config_file_paths(self.cli_config_path.as_deref())
+    }
+
     /// Get the fully resolved configuration
     pub fn get_config(&self) -> Value {
         self.config.clone()
@@ -40,12 +62,30 @@ impl ConfigManager {
 
     /// Reload the configuration from disk with variable resolution
     pub fn reload(&mut self) -> VaroResult<()> {
-        let mut config = Self::load_merged_config();
+        let mut config = Self::load_merged_config(self.cli_config_path.as_deref())?;
         resolve_env_vars_recursive(&mut config);
         self.config = config;
         Ok(())
     }
 
+    /// Reload the configuration from disk and report whether it changed. The values Varo saves
+    /// to the user config itself to remember the active presets are not counted as a change.
+    pub fn reload_if_changed(&mut self) -> VaroResult<bool> {
+        let previous = Self::without_session_values(self.config.clone());
+        self.reload()?;
+        Ok(Self::without_session_values(self.config.clone()) != previous)
+    }
+
+    /// Remove the values written by `save_user_config_value` while the app runs
+    fn without_session_values(mut config: Value) -> Value {
+        if let Some(env_presets) = config.get_mut("env_presets").and_then(Value::as_object_mut) {
+            for key in SESSION_KEYS {
+                env_presets.remove(key);
+            }
+        }
+        config
+    }
+
     /// Get the default configuration structure
     fn default_config() -> Value {
         json!({
@@ -53,6 +93,30 @@ impl ConfigManager {
                 "directories": [], 
                 "default_id": null 
             },
+            "access": {
+                "groups": {}
+            },
+            "nodes": {
+                "varo_path_fallback": true,
+                "load_threads": 8,
+                "load_timeout_secs": 10
+            },
+            "logs": {
+                "enabled": true,
+                "directory": null,
+                "max_files": 200,
+                "max_age_days": 14
+            },
+            "catalog_cache": {
+                "enabled": true,
+                "keep_on_scan_failure": false
+            },
+            "watch": {
+                "enabled": true,
+                "mode": "auto",
+                "debounce_ms": 300,
+                "poll_interval_secs": 5
+            },
             "ui": { 
                 "dark_mode": true, 
                 "show_groups": false, 
@@ -63,7 +127,7 @@ impl ConfigManager {
     }
 
     /// Load and merge configuration from all sources
-    fn load_merged_config() -> Value {
+    fn load_merged_config(cli_config_path: Option<&Path>) -> VaroResult<Value> {
         let mut config = Self::default_config();
         
         // Load and merge environment-specified config
@@ -76,12 +140,18 @@ impl ConfigManager {
             config::merge_configs(&mut config, &user_config);
         }
         
+        // Load and merge command-line config (highest priority)
+        if let Some(path) = cli_config_path {
+            let cli_config = config::load_required_config_file(path).map_err(VaroError::config)?;
+            config::merge_configs(&mut config, &cli_config);
+        }
+        
         // Debug output
         match serde_json::to_string_pretty(&config) {
-            Ok(pretty_json) => println!("[Config Manager] Loaded config:\n{}", pretty_json),
+            Ok(pretty_json) => varo_log!("[Config Manager] Loaded config:\n{}", pretty_json),
             Err(err) => eprintln!("[Config Manager] Failed to serialize config: {}", err),
         }
         
-        config
+        Ok(config)
     }
 }
\ No newline at end of file
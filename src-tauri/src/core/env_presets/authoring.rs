@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::{json, Map, Value};
+use crate::models::entities::EnvPreset;
+use crate::models::diagnostics::Diagnostic;
+use crate::utils::env::{parse_env_preset_json, preset_id_root};
+use crate::utils::files::write_file_atomic;
+use crate::utils::log::varo_log;
+
+/// Checks that `id` can be used as a preset id and as the name of its file.
+/// Ids are made of ASCII letters, digits, `_`, `-` and `.`, and do not start with a dot.
+pub fn validate_preset_id(id: &str) -> Result<(), String> {
+    if id.is_empty() {
+        return Err("Preset id must not be empty".to_string());
+    }
+    if id.starts_with('.') {
+        return Err(format!("Preset id '{}' must not start with a dot", id));
+    }
+    if let Some(c) = id.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))) {
+        return Err(format!("Preset id '{}' contains '{}'; use letters, digits, '_', '-' or '.'", id, c));
+    }
+    Ok(())
+}
+
+/// File a new preset with this id is written to
+pub fn preset_file_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+/// True when the preset was loaded from a JSON file and can be edited in place
+pub fn is_json_preset(preset: &EnvPreset) -> bool {
+    preset.filepath.as_deref()
+        .and_then(|f| Path::new(f).extension())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Reads a preset file as a JSON object, keeping its key order
+pub fn read_preset_json(path: &Path) -> Result<Map<String, Value>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+    match serde_json::from_str(&content) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => Err(format!("'{}' does not contain a JSON object", path.display())),
+        Err(e) => Err(format!("Failed to parse '{}': {}", path.display(), e)),
+    }
+}
+
+/// Merges `fields` into a preset object. Existing keys keep their position, new keys are
+/// appended and `null` removes a key. Keys the editor does not know about are left alone.
+pub fn merge_preset_fields(preset: &mut Map<String, Value>, fields: &Map<String, Value>) {
+    for (key, value) in fields {
+        if value.is_null() {
+            preset.shift_remove(key);
+        } else {
+            preset.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Returns `preset` with `id` and `name` replaced, keeping every other field.
+/// An `id` the file did not have is added as the first key.
+pub fn with_identity(preset: Map<String, Value>, id: &str, name: &str) -> Map<String, Value> {
+    let mut renamed = Map::new();
+    if !preset.contains_key("id") {
+        renamed.insert("id".to_string(), json!(id));
+    }
+    for (key, value) in preset {
+        match key.as_str() {
+            "id" => renamed.insert(key, json!(id)),
+            "name" => renamed.insert(key, json!(name)),
+            _ => renamed.insert(key, value),
+        };
+    }
+    if !renamed.contains_key("name") {
+        renamed.insert("name".to_string(), json!(name));
+    }
+    renamed
+}
+
+/// JSON for a preset loaded from a `.env` or `.sh` file, used when it is duplicated as a JSON preset
+pub fn preset_to_json(preset: &EnvPreset) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert("id".to_string(), json!(preset.id));
+    map.insert("name".to_string(), json!(preset.name));
+    if let Some(description) = &preset.description {
+        map.insert("description".to_string(), json!(description));
+    }
+    if !preset.extends.is_empty() {
+        map.insert("extends".to_string(), json!(preset.extends));
+    }
+    let env: Vec<Value> = preset.env.iter()
+        .map(|var| match var.operation.as_deref() {
+            None | Some("set") => json!({ "name": var.name, "value": var.value }),
+            Some(operation) => json!({ "name": var.name, "value": var.value, "operation": operation }),
+        })
+        .collect();
+    map.insert("env".to_string(), Value::Array(env));
+    map
+}
+
+/// Validates `preset` the way the loader would read it from `path`, then writes it atomically.
+/// Nothing is written when the preset has errors; the messages are returned instead.
+/// With `create_new`, an existing file at `path` is an error rather than replaced.
+pub fn write_preset_file(path: &Path, preset: &Map<String, Value>, create_new: bool) -> Result<(), String> {
+    if create_new && path.exists() {
+        return Err(format!("File already exists: {}", path.display()));
+    }
+
+    let mut content = serde_json::to_string_pretty(preset)
+        .map_err(|e| format!("Failed to serialize preset: {}", e))?;
+    content.push('\n');
+
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let loaded = parse_env_preset_json(&content, &path.to_path_buf(), preset_id_root(path), &mut diagnostics);
+    let errors: Vec<String> = diagnostics.iter()
+        .filter(|d| d.is_error())
+        .map(|d| d.message.clone())
+        .collect();
+    if loaded.is_none() || !errors.is_empty() {
+        return Err(format!("Invalid preset: {}", errors.join("; ")));
+    }
+
+    write_file_atomic(path, content.as_bytes())?;
+    varo_log!("[Preset Manager] Wrote preset file: {}", path.display());
+    Ok(())
+}
\ No newline at end of file
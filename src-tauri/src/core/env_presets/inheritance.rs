@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use crate::models::entities::EnvPreset;
+use crate::models::diagnostics::Diagnostic;
+use crate::utils::log::varo_log;
+
+/// Fill in `flattened_env` for every preset from its `extends` chain.
+///
+/// Each preset contributes its `provided_env` followed by its `env`.
+/// Parents are merged depth-first in the order they are listed, and a preset reached through
+/// several parents is only merged once, before the first preset that extends it. Missing parents
+/// and cycles are reported in `diagnostics` and skipped.
+pub fn resolve_extends(presets: &mut [EnvPreset], diagnostics: &mut Vec<Diagnostic>) {
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (i, preset) in presets.iter().enumerate() {
+        index.entry(preset.id.clone()).or_insert(i);
+    }
+
+    let mut reported: Vec<String> = Vec::new();
+    let mut report = |preset: &EnvPreset, message: String| {
+        if !reported.contains(&message) {
+            diagnostics.push(Diagnostic::error(preset.filepath.as_deref(), message.clone()).with_field("extends"));
+            reported.push(message);
+        }
+    };
+
+    let mut flattened = Vec::with_capacity(presets.len());
+    for i in 0..presets.len() {
+        let mut order = Vec::new();
+        linearize(i, presets, &index, &mut Vec::new(), &mut order, &mut report);
+
+        if order.len() > 1 {
+            let chain: Vec<&str> = order.iter().map(|&k| presets[k].id.as_str()).collect();
+            varo_log!("[Preset Manager] Preset '{}' inherits from: {}", presets[i].id, chain.join(" -> "));
+        }
+        flattened.push(order.iter()
+            .flat_map(|&k| presets[k].provided_env.iter().chain(&presets[k].env).cloned())
+            .collect());
+    }
+
+    for (preset, env) in presets.iter_mut().zip(flattened) {
+        preset.flattened_env = env;
+    }
+}
+
+/// Depth-first post-order walk of a preset's parents, ending with the preset itself
+fn linearize<F>(
+    i: usize,
+    presets: &[EnvPreset],
+    index: &HashMap<String, usize>,
+    stack: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+    report: &mut F,
+) where
+    F: FnMut(&EnvPreset, String),
+{
+    stack.push(i);
+    for parent_id in &presets[i].extends {
+        match index.get(parent_id) {
+            None => report(&presets[i], format!("Preset '{}' extends unknown preset '{}'", presets[i].id, parent_id)),
+            Some(&parent) if stack.contains(&parent) => {
+                let start = stack.iter().position(|&k| k == parent).unwrap_or(0);
+                let mut cycle: Vec<&str> = stack[start..].iter().map(|&k| presets[k].id.as_str()).collect();
+                // Start from the smallest id so the same cycle reads the same from every preset in it
+                let first = (0..cycle.len()).min_by_key(|&k| cycle[k]).unwrap_or(0);
+                cycle.rotate_left(first);
+                cycle.push(cycle[0]);
+                report(&presets[i], format!("Preset inheritance cycle: {}", cycle.join(" -> ")));
+            }
+            Some(&parent) => linearize(parent, presets, index, stack, order, report),
+        }
+    }
+    stack.pop();
+
+    if !order.contains(&i) {
+        order.push(i);
+    }
+}
@@ -1,31 +1,71 @@
-use std::collections::HashMap;
-use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::{Map, Value};
 use crate::models::entities::EnvPreset;
+use crate::models::diagnostics::Diagnostic;
 use crate::models::errors::{VaroError, VaroResult};
-use crate::utils::env::load_env_presets_in_dir;
+use crate::core::env_presets::authoring::{
+    is_json_preset, merge_preset_fields, preset_file_path, preset_to_json, read_preset_json,
+    validate_preset_id, with_identity, write_preset_file,
+};
+use crate::core::env_presets::inheritance::resolve_extends;
+use crate::core::env_presets::provider::resolve_providers;
+use crate::utils::duplicates::remove_duplicate_ids;
+use crate::utils::env::{check_env_references, get_current_env_vars, is_preset_file_name, list_preset_files, load_env_preset_file, preset_id_root};
+use crate::utils::expand::ExpandContext;
+use crate::utils::log::varo_log;
 
 #[derive(Debug)]
 pub struct PresetManager {
     presets: Vec<EnvPreset>,
-    selected_preset: Option<EnvPreset>,
+    /// Active presets in the order they are applied; the last one is the top-most layer
+    active_presets: Vec<EnvPreset>,
+    diagnostics: Vec<Diagnostic>,
+    /// Configured preset directories in scan order, with the files loaded from each.
+    /// New presets are written to one of them.
+    directories: Vec<PresetDir>,
 }
 
 impl PresetManager {
     pub fn new(env_presets_config: &Value) -> VaroResult<Self> {
-        let presets = Self::load_presets_from_env_config(env_presets_config)?;
-        Ok(Self {
-            presets,
-            selected_preset: None,
-        })
+        let mut manager = Self::empty();
+        manager.directories = Self::scan_directories(env_presets_config);
+        manager.rebuild_presets();
+        Ok(manager)
     }
 
     pub fn empty() -> Self {
         Self {
             presets: Vec::new(),
-            selected_preset: None,
+            active_presets: Vec::new(),
+            diagnostics: Vec::new(),
+            directories: Vec::new(),
         }
     }
 
+    /// Show presets saved by an earlier session until the preset directories are scanned.
+    /// The directories are taken from the config without being read.
+    pub fn from_cached(env_presets_config: &Value, presets: Vec<EnvPreset>) -> Self {
+        let mut manager = Self::empty();
+        manager.directories = Self::preset_directories(env_presets_config).into_iter()
+            .map(|path| PresetDir { path, files: BTreeMap::new(), error: None })
+            .collect();
+        manager.presets = presets;
+        varo_log!("[Preset Manager] Using {} cached presets", manager.presets.len());
+        manager
+    }
+
+    /// Why preset directories could not be read during the last scan, e.g. an unreachable share
+    pub fn get_directory_errors(&self) -> Vec<String> {
+        self.directories.iter().filter_map(|dir| dir.error.clone()).collect()
+    }
+
+    /// Problems found during the last preset scan
+    pub fn get_diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
     pub fn get_all_presets(&self) -> Vec<EnvPreset> {
         self.presets.clone()
     }
@@ -34,89 +74,420 @@ impl PresetManager {
         self.presets.iter().find(|p| p.id == id)
     }
 
+    fn find_preset(&self, id: &str) -> VaroResult<EnvPreset> {
+        self.get_preset(id)
+            .cloned()
+            .ok_or_else(|| VaroError::env_preset(format!("No EnvPreset found with id: {}", id)))
+    }
+
+    /// Make `id` the only active preset
     pub fn select_preset(&mut self, id: &str) -> VaroResult<()> {
-        if let Some(preset) = self.presets.iter().find(|p| p.id == id).cloned() {
-            self.selected_preset = Some(preset);
-            Ok(())
-        } else {
-            Err(VaroError::env_preset(format!("No EnvPreset found with id: {}", id)))
-        }
+        let preset = self.find_preset(id)?;
+        self.active_presets = vec![preset];
+        Ok(())
     }
 
+    /// The top-most active preset
     pub fn get_selected_preset(&self) -> Option<&EnvPreset> {
-        self.selected_preset.as_ref()
+        self.active_presets.last()
+    }
+
+    /// Active presets from the bottom layer to the top-most one
+    pub fn get_active_presets(&self) -> &[EnvPreset] {
+        &self.active_presets
+    }
+
+    /// Add a preset on top of the active stack. A preset that is already active is moved to the top.
+    pub fn push_preset(&mut self, id: &str) -> VaroResult<()> {
+        let preset = self.find_preset(id)?;
+        self.active_presets.retain(|p| p.id != id);
+        self.active_presets.push(preset);
+        Ok(())
+    }
+
+    /// Remove and return the top-most active preset
+    pub fn pop_preset(&mut self) -> Option<EnvPreset> {
+        self.active_presets.pop()
+    }
+
+    /// Reorder the active stack. `ids` lists every active preset exactly once, bottom layer first.
+    pub fn reorder_presets(&mut self, ids: &[String]) -> VaroResult<()> {
+        let mut active_ids: Vec<&str> = self.active_presets.iter().map(|p| p.id.as_str()).collect();
+        let mut requested: Vec<&str> = ids.iter().map(String::as_str).collect();
+        active_ids.sort_unstable();
+        requested.sort_unstable();
+        if active_ids != requested {
+            return Err(VaroError::env_preset(format!(
+                "Reordering must list each active preset once (active: {}; got: {})",
+                active_ids.join(", "),
+                ids.join(", ")
+            )));
+        }
+
+        let mut reordered = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(index) = self.active_presets.iter().position(|p| &p.id == id) {
+                reordered.push(self.active_presets.remove(index));
+            }
+        }
+        self.active_presets = reordered;
+        Ok(())
     }
 
-    fn load_presets_from_env_config(env_presets_config: &Value) -> VaroResult<Vec<EnvPreset>> {
-        println!("[Preset Manager] Starting preset loading process");
-        println!("[Preset Manager] Config received: {}", serde_json::to_string_pretty(env_presets_config).unwrap_or_else(|_| "Invalid JSON".to_string()));
+    /// Select the presets Varo should start with.
+    /// The user's last stack (`env_presets.last_active_ids`) is restored first, skipping ids that
+    /// no longer match a loaded preset. Without one, the last single selection
+    /// (`env_presets.last_selected_id`) takes precedence over `env_presets.default_id`.
+    pub fn select_initial_preset(&mut self, env_presets_config: &Value) -> &[EnvPreset] {
+        self.active_presets.clear();
+
+        let last_active_ids = env_presets_config.get("last_active_ids")
+            .and_then(|v| v.as_array())
+            .map(|ids| ids.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        for id in last_active_ids {
+            if self.push_preset(id).is_err() {
+                varo_log!("[Preset Manager] Warning: last_active_ids entry '{}' does not match any loaded preset, skipping", id);
+            }
+        }
+        if !self.active_presets.is_empty() {
+            varo_log!("[Preset Manager] Restored {} active presets from last_active_ids", self.active_presets.len());
+            return &self.active_presets;
+        }
+
+        let candidates = ["last_selected_id", "default_id"];
+
+        for key in candidates {
+            let id = match env_presets_config.get(key).and_then(|v| v.as_str()) {
+                Some(id) if !id.is_empty() => id,
+                _ => continue,
+            };
+
+            if self.select_preset(id).is_ok() {
+                varo_log!("[Preset Manager] Selected initial preset '{}' from {}", id, key);
+                return &self.active_presets;
+            }
+            varo_log!("[Preset Manager] Warning: {} '{}' does not match any loaded preset, skipping", key, id);
+        }
+
+        varo_log!("[Preset Manager] No initial preset selected");
+        &self.active_presets
+    }
+
+    /// Scan every configured preset directory
+    fn scan_directories(env_presets_config: &Value) -> Vec<PresetDir> {
+        varo_log!("[Preset Manager] Starting preset loading process");
+        varo_log!("[Preset Manager] Config received: {}", serde_json::to_string_pretty(env_presets_config).unwrap_or_else(|_| "Invalid JSON".to_string()));
         
         // If config is empty or null, return empty list
-        if env_presets_config.is_null() || env_presets_config.as_object().map_or(true, |o| o.is_empty()) {
-            println!("[Preset Manager] Config is null or empty, returning empty preset list");
-            return Ok(Vec::new());
+        if env_presets_config.is_null() || env_presets_config.as_object().is_none_or(|o| o.is_empty()) {
+            varo_log!("[Preset Manager] Config is null or empty, returning empty preset list");
+            return Vec::new();
         }
 
         // Get resolved directories (these should already be validated and existing)
-        let dirs = env_presets_config
-            .get("directories")
-            .and_then(|v| v.as_array())
-            .unwrap_or(&vec![])
-            .iter()
-            .filter_map(|v| v.as_str())
-            .map(std::path::PathBuf::from)
-            .collect::<Vec<_>>();
+        let dirs = Self::preset_directories(env_presets_config);
         
-        println!("[Preset Manager] Found {} directories to scan: {:?}", dirs.len(), dirs);
+        varo_log!("[Preset Manager] Found {} directories to scan: {:?}", dirs.len(), dirs);
         
         if dirs.is_empty() {
-            println!("[Preset Manager] No directories found in config, returning empty preset list");
-            return Ok(Vec::new());
+            varo_log!("[Preset Manager] No directories found in config, returning empty preset list");
         }
 
+        dirs.into_iter().map(PresetDir::scan).collect()
+    }
+
+    /// Combine the loaded files into the preset list. Directories are used in the configured order
+    /// and files in path order, so the first definition of an id wins. Parents can live in any
+    /// directory, so inheritance is resolved over the combined list.
+    fn rebuild_presets(&mut self) {
+        let mut diagnostics = Vec::new();
         let mut all_presets = Vec::new();
-        let mut errors = Vec::new();
-
-        for dir in dirs {
-            let dir_str = dir.to_str().unwrap_or_default();
-            println!("[Preset Manager] Scanning directory: {}", dir_str);
-            
-            match load_env_presets_in_dir(dir_str) {
-                Ok(presets) => {
-                    println!("[Preset Manager] Successfully loaded {} presets from {}", presets.len(), dir_str);
-                    for preset in &presets {
-                        println!("[Preset Manager]   - Found preset: '{}' (ID: {})", preset.name, preset.id);
-                    }
-                    all_presets.extend(presets);
-                },
-                Err(err) => {
-                    let error_msg = format!("Failed to load presets from {:?}: {}", dir, err);
-                    println!("[Preset Manager] Error: {}", error_msg);
-                    errors.push(error_msg);
-                }
+        for dir in &self.directories {
+            if let Some(error) = &dir.error {
+                diagnostics.push(Diagnostic::error(dir.path.to_str(), error.clone()));
+            }
+            for file in dir.files.values() {
+                diagnostics.extend(file.diagnostics.iter().cloned());
+                all_presets.extend(file.preset.iter().cloned());
             }
         }
 
-        // Log warnings but don't fail completely
-        for error in errors {
-            eprintln!("Warning: {}", error);
+        remove_duplicate_ids(&mut all_presets, "preset", |p| (p.id.as_str(), p.filepath.as_deref()), &mut diagnostics);
+        resolve_extends(&mut all_presets, &mut diagnostics);
+
+        let system_env = get_current_env_vars();
+        for preset in &all_presets {
+            let context = ExpandContext { preset_id: Some(preset.id.clone()), ..Default::default() };
+            check_env_references(&preset.flattened_env, &system_env, &context, preset.filepath.as_deref(), &mut diagnostics);
         }
 
-        println!("[Preset Manager] Preset loading complete. Total presets loaded: {}", all_presets.len());
+        varo_log!("[Preset Manager] Preset loading complete. Total presets loaded: {} ({} diagnostics)", all_presets.len(), diagnostics.len());
+        self.presets = all_presets;
+        self.diagnostics = diagnostics;
         
-        Ok(all_presets)
+        // Refresh the active presets, dropping those that no longer exist
+        let active_ids: Vec<String> = self.active_presets.iter().map(|p| p.id.clone()).collect();
+        self.active_presets = active_ids.iter()
+            .filter_map(|id| self.presets.iter().find(|p| &p.id == id).cloned())
+            .collect();
     }
 
     pub fn reload(&mut self, env_presets_config: &Value) -> VaroResult<()> {
-        self.presets = Self::load_presets_from_env_config(env_presets_config)?;
-        
-        // Clear selected preset if it no longer exists
-        if let Some(ref selected) = self.selected_preset {
-            if !self.presets.iter().any(|p| p.id == selected.id) {
-                self.selected_preset = None;
+        self.install_scan(Self::scan(env_presets_config));
+        Ok(())
+    }
+
+    /// Read the configured preset directories and run the providers of their presets. No manager
+    /// is involved, so this runs without holding the preset lock; see [`Self::install_scan`].
+    pub fn scan(env_presets_config: &Value) -> PresetScan {
+        PresetScan { directories: Self::scan_directories(env_presets_config) }
+    }
+
+    /// Replace the loaded presets with a scan, keeping the active presets that still exist
+    pub fn install_scan(&mut self, scan: PresetScan) {
+        self.directories = scan.directories;
+        self.rebuild_presets();
+    }
+
+    /// Read the preset files among `paths` that lie in one of `directories` (see
+    /// [`Self::get_directories`]), running their providers. Like [`Self::scan`] this runs without
+    /// holding the preset lock; see [`Self::install_changed_files`].
+    pub fn load_changed_files(directories: &[PathBuf], paths: &[PathBuf]) -> ChangedPresetFiles {
+        let files = paths.iter()
+            .filter(|path| is_preset_file_name(path))
+            .filter(|path| directories.iter().any(|dir| path.parent() == Some(dir.as_path())))
+            .map(|path| {
+                if path.is_file() {
+                    varo_log!("[Preset Manager] Reloading preset file: {}", path.display());
+                    (path.clone(), Some(PresetFile::load(path)))
+                } else {
+                    (path.clone(), None)
+                }
+            })
+            .collect();
+        ChangedPresetFiles { files }
+    }
+
+    /// Store files read by [`Self::load_changed_files`] and rebuild the preset list. Files that
+    /// no longer exist are dropped; files of directories that are no longer configured are
+    /// ignored. Returns whether anything changed.
+    pub fn install_changed_files(&mut self, changed_files: ChangedPresetFiles) -> bool {
+        let mut changed = false;
+        for (path, file) in changed_files.files {
+            let dir = match self.directories.iter_mut().find(|dir| path.parent() == Some(dir.path.as_path())) {
+                Some(dir) => dir,
+                None => continue,
+            };
+            match file {
+                Some(file) => {
+                    dir.files.insert(path, file);
+                    changed = true;
+                }
+                None => {
+                    if dir.files.remove(&path).is_some() {
+                        varo_log!("[Preset Manager] Preset file removed: {}", path.display());
+                        changed = true;
+                    }
+                }
             }
         }
-        
+        if changed {
+            self.rebuild_presets();
+        }
+        changed
+    }
+
+    /// Configured preset directories, in scan order
+    pub fn get_directories(&self) -> Vec<PathBuf> {
+        self.directories.iter().map(|dir| dir.path.clone()).collect()
+    }
+
+    fn preset_directories(env_presets_config: &Value) -> Vec<PathBuf> {
+        env_presets_config
+            .get("directories")
+            .and_then(|v| v.as_array())
+            .map(|dirs| dirs.iter().filter_map(|v| v.as_str()).map(PathBuf::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Directory new presets go to: `directory` if it is one of the configured preset
+    /// directories, otherwise the first configured one
+    fn target_directory(&self, directory: Option<&str>) -> VaroResult<PathBuf> {
+        let directory = match directory {
+            Some(directory) => directory,
+            None => return self.directories.first()
+                .map(|dir| dir.path.clone())
+                .ok_or_else(|| VaroError::env_preset("No preset directories are configured (env_presets.directories)")),
+        };
+
+        let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let requested = canonical(Path::new(directory));
+        self.directories.iter()
+            .find(|dir| canonical(&dir.path) == requested)
+            .map(|dir| dir.path.clone())
+            .ok_or_else(|| VaroError::env_preset(format!("'{}' is not one of the configured preset directories", directory)))
+    }
+
+    /// Checks that `id` is valid and not used by a loaded preset
+    fn check_new_id(&self, id: &str) -> VaroResult<()> {
+        validate_preset_id(id).map_err(VaroError::env_preset)?;
+        if let Some(existing) = self.get_preset(id) {
+            return Err(VaroError::env_preset(format!(
+                "A preset with id '{}' already exists ({})",
+                id,
+                existing.filepath.as_deref().unwrap_or("no file")
+            )));
+        }
         Ok(())
     }
+
+    /// Write a new JSON preset to `directory` (see [`Self::target_directory`]) as `<id>.json`.
+    /// `preset` must be an object with an `id` and a `name`; its keys are written in the given order.
+    /// Call [`Self::reload`] afterwards to load it. Returns the new file.
+    pub fn create_preset(&self, directory: Option<&str>, preset: &Value) -> VaroResult<PathBuf> {
+        let preset = preset.as_object()
+            .ok_or_else(|| VaroError::env_preset("Preset must be a JSON object"))?;
+        let id = preset.get("id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| VaroError::env_preset("Preset must have a string 'id'"))?;
+        self.check_new_id(id)?;
+
+        let path = preset_file_path(&self.target_directory(directory)?, id);
+        write_preset_file(&path, preset, true).map_err(VaroError::env_preset)?;
+        varo_log!("[Preset Manager] Created preset '{}'", id);
+        Ok(path)
+    }
+
+    /// Apply `fields` to the file of preset `id`, keeping its other fields and their order.
+    /// A `null` field removes that key. Only presets loaded from JSON files can be edited, and the
+    /// id cannot be changed. Call [`Self::reload`] afterwards. Returns the edited file.
+    pub fn update_preset(&self, id: &str, fields: &Value) -> VaroResult<PathBuf> {
+        let fields = fields.as_object()
+            .ok_or_else(|| VaroError::env_preset("Preset fields must be a JSON object"))?;
+        let preset = self.find_preset(id)?;
+        let path = match &preset.filepath {
+            Some(file) if is_json_preset(&preset) => PathBuf::from(file),
+            _ => return Err(VaroError::env_preset(format!(
+                "Preset '{}' was not loaded from a JSON file and cannot be edited; duplicate it to get an editable copy",
+                id
+            ))),
+        };
+        match fields.get("id") {
+            Some(Value::String(new_id)) if new_id != id => {
+                return Err(VaroError::env_preset(format!(
+                    "The id of preset '{}' cannot be changed; duplicate it under the new id instead",
+                    id
+                )));
+            }
+            Some(Value::String(_)) | None => {}
+            Some(_) => return Err(VaroError::env_preset("'id' must stay the preset's id")),
+        }
+
+        let mut json = read_preset_json(&path).map_err(VaroError::env_preset)?;
+        merge_preset_fields(&mut json, fields);
+        write_preset_file(&path, &json, false).map_err(VaroError::env_preset)?;
+        varo_log!("[Preset Manager] Updated preset '{}'", id);
+        Ok(path)
+    }
+
+    /// Copy preset `id` to `<new_id>.json` in `directory`, named `name` or "<name> (copy)".
+    /// JSON presets are copied with all their fields; presets from `.env` and `.sh` files are
+    /// converted to JSON. Call [`Self::reload`] afterwards. Returns the new file.
+    pub fn duplicate_preset(&self, id: &str, new_id: &str, name: Option<&str>, directory: Option<&str>) -> VaroResult<PathBuf> {
+        let source = self.find_preset(id)?;
+        self.check_new_id(new_id)?;
+        let name = name.map(str::to_string).unwrap_or_else(|| format!("{} (copy)", source.name));
+
+        let json = match &source.filepath {
+            Some(file) if is_json_preset(&source) => read_preset_json(Path::new(file)).map_err(VaroError::env_preset)?,
+            _ => preset_to_json(&source),
+        };
+        let json: Map<String, Value> = with_identity(json, new_id, &name);
+
+        let path = preset_file_path(&self.target_directory(directory)?, new_id);
+        write_preset_file(&path, &json, true).map_err(VaroError::env_preset)?;
+        varo_log!("[Preset Manager] Duplicated preset '{}' as '{}'", id, new_id);
+        Ok(path)
+    }
+
+    /// Delete the file of preset `id`. Presets that extend it report the missing parent after
+    /// the next [`Self::reload`], which also removes it from the active stack. Returns the deleted file.
+    pub fn delete_preset(&self, id: &str) -> VaroResult<PathBuf> {
+        let preset = self.find_preset(id)?;
+        let path = preset.filepath
+            .map(PathBuf::from)
+            .ok_or_else(|| VaroError::env_preset(format!("Preset '{}' has no file to delete", id)))?;
+        fs::remove_file(&path)
+            .map_err(|e| VaroError::env_preset(format!("Failed to delete '{}': {}", path.display(), e)))?;
+        varo_log!("[Preset Manager] Deleted preset '{}' ({})", id, path.display());
+        Ok(path)
+    }
+}
+
+/// Preset directories read by [`PresetManager::scan`], ready to be installed in one step
+#[derive(Debug)]
+pub struct PresetScan {
+    directories: Vec<PresetDir>,
+}
+
+/// Preset files read by [`PresetManager::load_changed_files`]; `None` for files that are gone
+#[derive(Debug)]
+pub struct ChangedPresetFiles {
+    files: Vec<(PathBuf, Option<PresetFile>)>,
+}
+
+/// A preset directory and the files loaded from it, in path order
+#[derive(Debug)]
+struct PresetDir {
+    path: PathBuf,
+    files: BTreeMap<PathBuf, PresetFile>,
+    /// Why the directory could not be read, if it could not
+    error: Option<String>,
+}
+
+impl PresetDir {
+    fn scan(path: PathBuf) -> Self {
+        let dir_str = path.to_str().unwrap_or_default().to_string();
+        varo_log!("[Preset Manager] Scanning directory: {}", dir_str);
+
+        match list_preset_files(&dir_str) {
+            Ok(paths) => {
+                let files: BTreeMap<PathBuf, PresetFile> = paths.into_iter()
+                    .map(|file_path| {
+                        let file = PresetFile::load(&file_path);
+                        (file_path, file)
+                    })
+                    .collect();
+                varo_log!("[Preset Manager] Successfully loaded {} presets from {}", files.values().filter(|f| f.preset.is_some()).count(), dir_str);
+                for preset in files.values().filter_map(|f| f.preset.as_ref()) {
+                    varo_log!("[Preset Manager]   - Found preset: '{}' (ID: {})", preset.name, preset.id);
+                }
+                Self { path, files, error: None }
+            }
+            Err(err) => {
+                // Record the problem but keep scanning the remaining directories
+                varo_log!("[Preset Manager] Error: Failed to load presets from {:?}: {}", path, err);
+                Self { path, files: BTreeMap::new(), error: Some(err) }
+            }
+        }
+    }
+}
+
+/// A preset file and the problems found while loading it
+#[derive(Debug)]
+struct PresetFile {
+    preset: Option<EnvPreset>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl PresetFile {
+    /// Load a preset and run its provider. Provider output only depends on the file itself,
+    /// so it is kept until the file changes.
+    fn load(path: &PathBuf) -> Self {
+        let mut diagnostics = Vec::new();
+        let mut presets: Vec<EnvPreset> = load_env_preset_file(path, preset_id_root(path), &mut diagnostics).into_iter().collect();
+        resolve_providers(&mut presets, &mut diagnostics);
+        Self { preset: presets.pop(), diagnostics }
+    }
 }
\ No newline at end of file
@@ -1,3 +1,6 @@
+pub mod authoring;
+pub mod inheritance;
 pub mod manager;
+pub mod provider;
 
 pub use manager::PresetManager;
\ No newline at end of file
@@ -0,0 +1,232 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde_json::{json, Map, Value};
+use crate::models::entities::{EnvPreset, EnvProvider, EnvVar};
+use crate::models::diagnostics::Diagnostic;
+use crate::models::errors::VaroError;
+use crate::utils::config::get_user_varo_dir;
+use crate::utils::hasher::Hasher;
+use crate::utils::program::is_bare_name;
+use crate::utils::log::varo_log;
+
+/// Shell bookkeeping variables that resolver output always contains but that are not part of the environment
+const IGNORED_VARIABLES: [&str; 4] = ["_", "SHLVL", "PWD", "OLDPWD"];
+
+/// Run the `provider` command of every preset that has one and store its variables in `provided_env`.
+/// Presets whose provider fails are removed and reported in `diagnostics`, since they would be
+/// missing part of their environment.
+pub fn resolve_providers(presets: &mut Vec<EnvPreset>, diagnostics: &mut Vec<Diagnostic>) {
+    let cache_dir = get_user_varo_dir().map(|dir| dir.join("cache").join("providers"));
+
+    presets.retain_mut(|preset| {
+        let provider = match &preset.provider {
+            Some(provider) => provider,
+            None => return true,
+        };
+
+        match run_provider_cached(provider, preset.filepath.as_deref(), cache_dir.as_deref()) {
+            Ok(env) => {
+                varo_log!("[Preset Manager] Provider for '{}' produced {} variables", preset.id, env.len());
+                preset.provided_env = env;
+                true
+            }
+            Err(e) => {
+                let message = VaroError::env_preset(format!("Provider for preset '{}' failed: {}", preset.id, e)).to_string();
+                varo_log!("[Preset Manager] Error: {}", message);
+                diagnostics.push(Diagnostic::error(preset.filepath.as_deref(), message).with_field("provider"));
+                false
+            }
+        }
+    });
+}
+
+/// Returns cached provider output when it is younger than `cache_ttl_secs`, otherwise runs the command
+/// and refreshes the cache. The cache key is a hash of the expanded command, arguments, working
+/// directory, format and excluded names.
+fn run_provider_cached(provider: &EnvProvider, preset_file: Option<&str>, cache_dir: Option<&Path>) -> Result<Vec<EnvVar>, String> {
+    let preset_dir = preset_file.and_then(|f| Path::new(f).parent());
+    let program = resolve_against(&provider.command, preset_dir, true);
+    let cwd = provider.cwd.as_deref().map(|cwd| resolve_against(cwd, preset_dir, false)).or_else(|| preset_dir.map(Path::to_path_buf));
+
+    let program_str = program.to_string_lossy();
+    let cwd_str = cwd.as_ref().map(|c| c.to_string_lossy().to_string()).unwrap_or_default();
+    let mut key_parts: Vec<&str> = vec![&program_str, &cwd_str, &provider.format];
+    key_parts.extend(provider.args.iter().map(String::as_str));
+    key_parts.push("--exclude");
+    key_parts.extend(provider.exclude.iter().map(String::as_str));
+    let cache_file = cache_dir
+        .filter(|_| provider.cache_ttl_secs > 0)
+        .map(|dir| dir.join(format!("{}.json", Hasher::hash_parts(&key_parts))));
+
+    if let Some(cached) = cache_file.as_deref().and_then(|file| read_cache(file, provider.cache_ttl_secs)) {
+        varo_log!("[Preset Manager] Using cached provider output for {}", program_str);
+        return Ok(cached);
+    }
+
+    let output = run_with_timeout(&program, &provider.args, cwd.as_deref(), Duration::from_secs(provider.timeout_secs))?;
+    let values = parse_provider_output(&output, &provider.format)?;
+    let values: Vec<(String, Option<String>)> = values.into_iter()
+        .filter(|(name, _)| !IGNORED_VARIABLES.contains(&name.as_str()) && !provider.exclude.contains(name))
+        .collect();
+
+    if let Some(file) = &cache_file {
+        write_cache(file, &values);
+    }
+    Ok(to_env_vars(values))
+}
+
+/// Relative program paths containing a folder, and relative working directories, are resolved
+/// against the preset file's folder. Bare program names are left for the OS to find on `PATH`.
+fn resolve_against(path: &str, preset_dir: Option<&Path>, is_program: bool) -> PathBuf {
+    let relative = Path::new(path).is_relative() && !(is_program && is_bare_name(path));
+    match preset_dir {
+        Some(dir) if relative => Path::new(path).components()
+            .filter(|c| *c != Component::CurDir)
+            .fold(dir.to_path_buf(), |joined, c| joined.join(c)),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn run_with_timeout(program: &Path, args: &[String], cwd: Option<&Path>, timeout: Duration) -> Result<String, String> {
+    varo_log!("[Preset Manager] Running provider: {} {:?}", program.display(), args);
+
+    let mut command = Command::new(program);
+    command.args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+
+    let mut child = command.spawn()
+        .map_err(|e| format!("Failed to start '{}': {}", program.display(), e))?;
+
+    // Read both pipes on their own threads so a chatty command cannot fill a pipe and stall
+    let read_pipe = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            String::from_utf8_lossy(&buffer).to_string()
+        })
+    };
+    let stdout = read_pipe(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = read_pipe(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                // The reader threads are left to finish on their own; a grandchild may still hold the pipes open
+                return Err(format!(
+                    "'{}' did not finish within {} seconds",
+                    program.display(),
+                    timeout.as_secs()
+                ));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(20)),
+            Err(e) => return Err(format!("Failed to wait for '{}': {}", program.display(), e)),
+        }
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let detail = stderr.trim().lines().last().unwrap_or("no error output");
+        return Err(format!("'{}' exited with {}: {}", program.display(), status, detail));
+    }
+    Ok(stdout)
+}
+
+/// Parses provider output into variable values (`None` removes the variable).
+/// `env` reads `KEY=VALUE` lines as printed by `printenv`, `env` or `set`; lines without a `=`
+/// continue the previous value. `json` reads an object of names to strings (or `null`).
+/// `auto` picks `json` when the output starts with `{`.
+fn parse_provider_output(output: &str, format: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let is_json = match format {
+        "json" => true,
+        "env" => false,
+        _ => output.trim_start().starts_with('{'),
+    };
+
+    if is_json {
+        let json: Value = serde_json::from_str(output.trim())
+            .map_err(|e| format!("Output is not valid JSON: {}", e))?;
+        let object = json.as_object()
+            .ok_or_else(|| "JSON output must be an object of variable names to values".to_string())?;
+        return object.iter()
+            .map(|(name, value)| match value {
+                Value::String(s) => Ok((name.clone(), Some(s.clone()))),
+                Value::Null => Ok((name.clone(), None)),
+                Value::Number(_) | Value::Bool(_) => Ok((name.clone(), Some(value.to_string()))),
+                other => Err(format!("Value of '{}' must be a string, found: {}", name, other)),
+            })
+            .collect();
+    }
+
+    let mut values: Vec<(String, Option<String>)> = Vec::new();
+    for line in output.lines() {
+        match line.split_once('=') {
+            Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                values.push((name.to_string(), Some(value.to_string())));
+            }
+            _ => {
+                if let Some((_, Some(previous))) = values.last_mut() {
+                    previous.push('\n');
+                    previous.push_str(line);
+                }
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Provider values are literal, so `$` is escaped before the entries go through `${VAR}` expansion
+fn to_env_vars(values: Vec<(String, Option<String>)>) -> Vec<EnvVar> {
+    values.into_iter()
+        .map(|(name, value)| match value {
+            Some(value) => EnvVar { name, value: value.replace('$', "$$"), operation: Some("set".to_string()) },
+            None => EnvVar { name, value: String::new(), operation: Some("unset".to_string()) },
+        })
+        .collect()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn read_cache(file: &Path, ttl_secs: u64) -> Option<Vec<EnvVar>> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(file).ok()?).ok()?;
+    let created_at = json.get("created_at")?.as_u64()?;
+    if now_secs().saturating_sub(created_at) >= ttl_secs {
+        return None;
+    }
+    let values = json.get("values")?.as_object()?
+        .iter()
+        .map(|(name, value)| (name.clone(), value.as_str().map(|s| s.to_string())))
+        .collect();
+    Some(to_env_vars(values))
+}
+
+fn write_cache(file: &Path, values: &[(String, Option<String>)]) {
+    let values: Map<String, Value> = values.iter()
+        .map(|(name, value)| (name.clone(), value.clone().map_or(Value::Null, Value::String)))
+        .collect();
+    let contents = json!({ "created_at": now_secs(), "values": values }).to_string();
+
+    let result = file.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(file, contents));
+    if let Err(e) = result {
+        eprintln!("Warning: Failed to write provider cache '{}': {}", file.display(), e);
+    }
+}
@@ -1,7 +1,10 @@
 pub mod app_state;
+pub mod catalog;
 pub mod config;
 pub mod env_presets;
 pub mod nodes;
+pub mod processes;
 pub mod system;
+pub mod watch;
 
-pub use app_state::VaroCore;
\ No newline at end of file
+pub use app_state::{CoreOptions, VaroCore};
\ No newline at end of file
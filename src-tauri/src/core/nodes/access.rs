@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use serde_json::Value;
+use crate::models::entities::{Access, NodeAccessReport, VaroNode};
+use crate::core::system::SystemInfo;
+
+/// Evaluates node `access` rules against the current user and platform.
+/// Entries in `allow`/`deny` may use `*` and `?` wildcards, and `@name` refers to a group:
+/// either an OS group the user belongs to, or a group defined under `access.groups` in config.
+#[derive(Debug, Clone)]
+pub struct AccessPolicy {
+    username: String,
+    platform: String,
+    os_groups: Vec<String>,
+    config_groups: HashMap<String, Vec<String>>,
+}
+
+impl AccessPolicy {
+    pub fn new(system_info: &SystemInfo) -> Self {
+        Self {
+            username: system_info.get_username().to_string(),
+            platform: system_info.get_platform().to_string(),
+            os_groups: system_info.get_groups().to_vec(),
+            config_groups: HashMap::new(),
+        }
+    }
+
+    /// Load group definitions from the `access` config section, e.g. `{"groups": {"leads": ["john", "jane"]}}`
+    pub fn set_config_groups(&mut self, access_config: &Value) {
+        self.config_groups = access_config
+            .get("groups")
+            .and_then(|v| v.as_object())
+            .map(|groups| {
+                groups.iter()
+                    .map(|(name, members)| {
+                        let members = members.as_array()
+                            .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+                            .unwrap_or_default();
+                        (name.to_lowercase(), members)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+
+    pub fn is_allowed(&self, node: &VaroNode) -> bool {
+        self.evaluate(node.access.as_ref()).is_empty()
+    }
+
+    /// Explains whether a node is visible to the current user and why
+    pub fn explain(&self, node: &VaroNode) -> NodeAccessReport {
+        let reasons = self.evaluate(node.access.as_ref());
+        NodeAccessReport {
+            node_id: node.id.clone(),
+            node_name: node.name.clone(),
+            username: self.username.clone(),
+            platform: self.platform.clone(),
+            allowed: reasons.is_empty(),
+            reasons,
+        }
+    }
+
+    /// Returns the list of reasons the node is denied; an empty list means access is granted
+    fn evaluate(&self, access: Option<&Access>) -> Vec<String> {
+        let access = match access {
+            Some(access) => access,
+            None => return Vec::new(),
+        };
+
+        let mut reasons = Vec::new();
+
+        if !access.platforms.is_empty()
+            && !access.platforms.iter().any(|p| wildcard_match(p, &self.platform))
+        {
+            reasons.push(format!(
+                "Platform '{}' is not in the node's platforms {:?}",
+                self.platform, access.platforms
+            ));
+        }
+
+        if let Some(entry) = access.deny.iter().find(|entry| self.matches_user(entry)) {
+            reasons.push(format!("User '{}' matches deny entry '{}'", self.username, entry));
+        }
+
+        if !access.allow.is_empty() && !access.allow.iter().any(|entry| self.matches_user(entry)) {
+            reasons.push(format!(
+                "User '{}' does not match any allow entry {:?}",
+                self.username, access.allow
+            ));
+        }
+
+        reasons
+    }
+
+    /// Checks a single allow/deny entry against the current user
+    fn matches_user(&self, entry: &str) -> bool {
+        match entry.strip_prefix('@') {
+            Some(group) => {
+                let os_match = self.os_groups.iter().any(|g| wildcard_match(group, g));
+                let config_match = self.config_groups.iter()
+                    .filter(|(name, _)| wildcard_match(group, name))
+                    .any(|(_, members)| members.iter().any(|m| wildcard_match(m, &self.username)));
+                os_match || config_match
+            }
+            None => wildcard_match(entry, &self.username),
+        }
+    }
+}
+
+/// Case-insensitive glob match supporting `*` (any run of characters) and `?` (one character)
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
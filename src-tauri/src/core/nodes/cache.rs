@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::models::entities::{ScanStats, VaroNode};
+use crate::models::diagnostics::Diagnostic;
+use crate::core::nodes::icons::IconStore;
+use crate::utils::files::FileStamp;
+use crate::utils::hasher::Hasher;
+use crate::utils::node::parse_node_json;
+
+/// A node file and the problems found while loading it
+#[derive(Debug, Clone)]
+pub struct NodeFile {
+    pub node: Option<VaroNode>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl NodeFile {
+    fn parse(content: &str, path: &PathBuf, varo_path: &Path, icons: &IconStore) -> Self {
+        let mut diagnostics = Vec::new();
+        let node = parse_node_json(content, path, varo_path, &|icon_file| icons.load_file(icon_file), &mut diagnostics);
+        Self { node, diagnostics }
+    }
+
+    fn unreadable(path: &Path, error: std::io::Error) -> Self {
+        let diagnostic = Diagnostic::error(path.to_str(), format!("Failed to read file: {}", error));
+        Self { node: None, diagnostics: vec![diagnostic] }
+    }
+}
+
+/// A loaded node file with what it was loaded from
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// VARO_PATH the file was loaded from, which relative icons and `${varo.path}` depend on
+    varo_path: PathBuf,
+    stamp: FileStamp,
+    hash: String,
+    /// Stamp of the icon file the node's icon was encoded from
+    icon_stamp: Option<FileStamp>,
+    file: NodeFile,
+}
+
+impl CacheEntry {
+    /// True when the icon file still looks the way it did when it was loaded, and its content is
+    /// still in `icons`
+    fn icon_is_current(&self, icons: &IconStore) -> bool {
+        let node = match self.file.node.as_ref() {
+            Some(node) => node,
+            None => return true,
+        };
+        match node.icon_file.as_deref() {
+            Some(icon_file) => {
+                FileStamp::of(Path::new(icon_file)) == self.icon_stamp && (node.icon.is_empty() || icons.contains(&node.icon))
+            }
+            None => true,
+        }
+    }
+}
+
+/// How a node file was loaded, counted in [`ScanStats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoadKind {
+    Reused,
+    Unchanged,
+    Parsed,
+    TimedOut,
+}
+
+/// The result of loading one node file, to be stored with [`NodeCache::record`]. Loading only
+/// reads the file system, so it can run on any thread.
+#[derive(Debug)]
+pub struct FileLoad {
+    kind: LoadKind,
+    /// `None` when the file could not be read
+    entry: Option<Arc<CacheEntry>>,
+    file: NodeFile,
+}
+
+impl FileLoad {
+    /// Load the node file at `path` found in `varo_path`, reusing `cached` (from
+    /// [`NodeCache::lookup`]) when the file and its icon have not changed. Icons are added to `icons`.
+    pub fn load(path: &Path, varo_path: &Path, cached: Option<Arc<CacheEntry>>, icons: &IconStore) -> Self {
+        let stamp = FileStamp::of(path);
+        let cached = cached.filter(|entry| entry.icon_is_current(icons));
+
+        if let (Some(entry), Some(stamp)) = (&cached, stamp) {
+            if entry.stamp == stamp {
+                return Self { kind: LoadKind::Reused, file: entry.file.clone(), entry: cached };
+            }
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => return Self { kind: LoadKind::Parsed, entry: None, file: NodeFile::unreadable(path, e) },
+        };
+        let hash = Hasher::hash_content(content.as_bytes());
+        let stamp = stamp.unwrap_or(FileStamp { len: content.len() as u64, modified: None });
+
+        if let Some(entry) = cached.filter(|entry| entry.hash == hash) {
+            // Touched or copied over with the same content
+            let mut entry = CacheEntry::clone(&entry);
+            entry.stamp = stamp;
+            if let Some(node) = entry.file.node.as_mut() {
+                node.date_modified = stamp.modified_millis();
+            }
+            return Self { kind: LoadKind::Unchanged, file: entry.file.clone(), entry: Some(Arc::new(entry)) };
+        }
+
+        let file = NodeFile::parse(&content, &path.to_path_buf(), varo_path, icons);
+        let icon_stamp = file.node.as_ref()
+            .and_then(|node| node.icon_file.as_deref())
+            .and_then(|icon_file| FileStamp::of(Path::new(icon_file)));
+        let entry = CacheEntry {
+            varo_path: varo_path.to_path_buf(),
+            stamp,
+            hash,
+            icon_stamp,
+            file: file.clone(),
+        };
+        Self { kind: LoadKind::Parsed, entry: Some(Arc::new(entry)), file }
+    }
+
+    /// A file that was still being read after `timeout`. The version loaded earlier is kept
+    /// when there is one.
+    pub fn timed_out(path: &Path, cached: Option<Arc<CacheEntry>>, timeout: Duration) -> Self {
+        let (mut file, diagnostic) = match &cached {
+            Some(entry) => (entry.file.clone(), Diagnostic::warning(
+                path.to_str(),
+                format!("Reading the file took longer than {} s; showing the version loaded earlier", timeout.as_secs_f32()),
+            )),
+            None => (NodeFile { node: None, diagnostics: Vec::new() }, Diagnostic::error(
+                path.to_str(),
+                format!("Reading the file took longer than {} s", timeout.as_secs_f32()),
+            )),
+        };
+        file.diagnostics.push(diagnostic);
+        Self { kind: LoadKind::TimedOut, entry: cached, file }
+    }
+}
+
+/// Node files from earlier scans, by path. A file whose size and modification time are unchanged
+/// is reused without being read; one with a new timestamp is read and hashed, and only parsed
+/// again (which re-encodes its icon) when its content differs. Entries are kept across `VARO_PATH`
+/// switches, so switching back to a preset is as fast as staying on it. Cloning is cheap, so a
+/// scan can work on a copy while the manager stays available.
+#[derive(Debug, Clone, Default)]
+pub struct NodeCache {
+    entries: HashMap<PathBuf, Arc<CacheEntry>>,
+}
+
+impl NodeCache {
+    /// The entry for `path` when it was loaded from `varo_path`
+    pub fn lookup(&self, path: &Path, varo_path: &Path) -> Option<Arc<CacheEntry>> {
+        self.entries.get(path).filter(|entry| entry.varo_path == varo_path).cloned()
+    }
+
+    /// Store a loaded file and return it. `stats` records how it was loaded.
+    pub fn record(&mut self, path: PathBuf, load: FileLoad, stats: &mut ScanStats) -> NodeFile {
+        match load.kind {
+            LoadKind::Reused => stats.reused += 1,
+            LoadKind::Unchanged => stats.unchanged += 1,
+            LoadKind::Parsed => stats.parsed += 1,
+            LoadKind::TimedOut => stats.timed_out += 1,
+        }
+        match load.entry {
+            Some(entry) => {
+                self.entries.insert(path, entry);
+            }
+            None => {
+                self.entries.remove(&path);
+            }
+        }
+        load.file
+    }
+
+    /// Keys of the icons used by the cached files
+    pub fn icon_keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.values()
+            .filter_map(|entry| entry.file.node.as_ref())
+            .map(|node| node.icon.as_str())
+    }
+
+    /// Forget a file that was removed
+    pub fn remove(&mut self, path: &Path) {
+        self.entries.remove(path);
+    }
+
+    /// Forget files from an earlier scan of `varo_path` that `is_listed` no longer reports
+    pub fn prune<F: Fn(&Path) -> bool>(&mut self, varo_path: &Path, is_listed: F) {
+        self.entries.retain(|path, entry| entry.varo_path != varo_path || is_listed(path));
+    }
+
+    /// Forget every file, so the next scan parses everything again
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
\ No newline at end of file
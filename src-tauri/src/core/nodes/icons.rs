@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use crate::utils::files::FileStamp;
+use crate::utils::icon::{load_icon, resize_icon, Icon};
+
+/// Sizes icons are scaled to. A requested size is rounded up to the next one, so a few copies per
+/// icon serve every request; larger requests get the original.
+const ICON_SIZES: [u32; 8] = [16, 24, 32, 48, 64, 128, 256, 512];
+
+/// Node icons by content hash. Nodes only carry the key, and the frontend fetches the icon itself
+/// through the `varo-icon` protocol. The store is shared with the scans of its manager, which add
+/// icons while the current ones are being served.
+#[derive(Debug, Default)]
+pub struct IconStore {
+    icons: RwLock<HashMap<String, Arc<Icon>>>,
+    /// Key of each icon file as of its stamp, so a file used by many nodes is read once
+    files: Mutex<HashMap<PathBuf, (FileStamp, String)>>,
+    /// Scaled copies by key and size
+    scaled: Mutex<HashMap<(String, u32), Arc<Icon>>>,
+}
+
+impl IconStore {
+    /// Load the icon file at `path` and return its key. The file is only read again when its size
+    /// or modification time changed.
+    pub fn load_file(&self, path: &Path) -> Result<String, String> {
+        let stamp = FileStamp::of(path);
+        if let Some(stamp) = stamp {
+            let known = lock(&self.files).get(path).filter(|(known, _)| *known == stamp).map(|(_, key)| key.clone());
+            if let Some(key) = known.filter(|key| self.contains(key)) {
+                return Ok(key);
+            }
+        }
+
+        let key = self.insert(load_icon(path)?);
+        if let Some(stamp) = stamp {
+            lock(&self.files).insert(path.to_path_buf(), (stamp, key.clone()));
+        }
+        Ok(key)
+    }
+
+    /// Add an icon, keeping the stored copy when there is one with the same content. Returns its key.
+    pub fn insert(&self, icon: Icon) -> String {
+        let key = icon.key.clone();
+        self.icons.write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(icon));
+        key
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.icons.read().unwrap_or_else(|e| e.into_inner()).contains_key(key)
+    }
+
+    /// The icon with `key`, scaled down to fit `size` pixels when a size is given and the icon is
+    /// a larger raster image
+    pub fn get(&self, key: &str, size: Option<u32>) -> Option<Arc<Icon>> {
+        let icon = self.icons.read().unwrap_or_else(|e| e.into_inner()).get(key).cloned()?;
+        let size = match size.and_then(|size| ICON_SIZES.into_iter().find(|bucket| *bucket >= size)) {
+            Some(size) => size,
+            None => return Some(icon),
+        };
+
+        let scaled_key = (key.to_string(), size);
+        if let Some(scaled) = lock(&self.scaled).get(&scaled_key) {
+            return Some(scaled.clone());
+        }
+        let scaled = resize_icon(&icon, size).map(Arc::new).unwrap_or(icon);
+        lock(&self.scaled).insert(scaled_key, scaled.clone());
+        Some(scaled)
+    }
+
+    /// Drop the icons whose key is not in `used`, with their scaled copies
+    pub fn retain(&self, used: &HashSet<&str>) {
+        self.icons.write().unwrap_or_else(|e| e.into_inner()).retain(|key, _| used.contains(key.as_str()));
+        lock(&self.files).retain(|_, (_, key)| used.contains(key.as_str()));
+        lock(&self.scaled).retain(|(key, _), _| used.contains(key.as_str()));
+    }
+
+    /// Forget which files the icons came from, so the next scan reads every icon file again
+    pub fn forget_files(&self) {
+        lock(&self.files).clear();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
\ No newline at end of file
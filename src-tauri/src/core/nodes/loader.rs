@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use crate::core::nodes::cache::{CacheEntry, FileLoad, NodeCache};
+use crate::core::nodes::icons::IconStore;
+use crate::models::entities::ScanStats;
+use crate::utils::log::varo_log;
+use crate::utils::node::list_node_files;
+
+const DEFAULT_LOAD_THREADS: usize = 8;
+const DEFAULT_LOAD_TIMEOUT_SECS: u64 = 10;
+
+/// How node files are loaded, from the `nodes` config section
+#[derive(Debug, Clone)]
+pub struct LoadSettings {
+    /// Files read at the same time
+    pub threads: usize,
+    /// How long a single file (and its icon) may take to read
+    pub timeout: Duration,
+}
+
+impl Default for LoadSettings {
+    fn default() -> Self {
+        Self {
+            threads: DEFAULT_LOAD_THREADS,
+            timeout: Duration::from_secs(DEFAULT_LOAD_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl LoadSettings {
+    pub fn from_config(nodes_config: &Value) -> Self {
+        let defaults = Self::default();
+        Self {
+            threads: nodes_config.get("load_threads")
+                .and_then(|v| v.as_u64())
+                .filter(|threads| *threads > 0)
+                .map(|threads| threads as usize)
+                .unwrap_or(defaults.threads),
+            timeout: nodes_config.get("load_timeout_secs")
+                .and_then(|v| v.as_u64())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.timeout),
+        }
+    }
+}
+
+/// A scan of the nodes folder prepared by [`NodeManager::prepare_scan`](crate::core::nodes::NodeManager::prepare_scan).
+/// It owns everything it needs, so it runs without holding the manager's lock.
+#[derive(Debug)]
+pub struct NodeScan {
+    varo_path: Option<String>,
+    cache: NodeCache,
+    icons: Arc<IconStore>,
+    settings: LoadSettings,
+    started: Instant,
+}
+
+/// Node files read by a [`NodeScan`], ready to be installed in the manager in one step
+#[derive(Debug)]
+pub struct ScannedNodes {
+    pub varo_path: Option<String>,
+    /// Loaded files in path order, or why the nodes folder could not be read
+    pub files: Result<Vec<(PathBuf, FileLoad)>, String>,
+    /// Listing and loading statistics; the cache counts are added on install
+    pub stats: ScanStats,
+    pub started: Instant,
+}
+
+impl NodeScan {
+    pub fn new(varo_path: Option<String>, cache: NodeCache, icons: Arc<IconStore>, settings: LoadSettings) -> Self {
+        Self { varo_path, cache, icons, settings, started: Instant::now() }
+    }
+
+    /// List and load the files in `VARO_PATH/nodes`
+    pub fn run(self) -> ScannedNodes {
+        let mut stats = ScanStats::default();
+        let files = match &self.varo_path {
+            None => Ok(Vec::new()),
+            Some(varo_path) => {
+                let nodes_path = format!("{}/nodes", varo_path);
+                varo_log!("[Node Manager] Loading nodes from: {}", nodes_path);
+                stats.varo_path = Some(varo_path.clone());
+
+                let listed = Instant::now();
+                list_node_files(&nodes_path).map(|paths| {
+                    stats.list_ms = listed.elapsed().as_millis() as u64;
+                    stats.files = paths.len();
+                    let loaded = Instant::now();
+                    let files = load_files(paths, Path::new(varo_path), &self.cache, &self.icons, &self.settings);
+                    stats.load_ms = loaded.elapsed().as_millis() as u64;
+                    files
+                })
+            }
+        };
+        ScannedNodes { varo_path: self.varo_path, files, stats, started: self.started }
+    }
+}
+
+struct Job {
+    path: PathBuf,
+    cached: Option<Arc<CacheEntry>>,
+}
+
+enum WorkerMessage {
+    Started(usize),
+    Done(usize, Box<FileLoad>),
+}
+
+/// Load node files found in `varo_path` on up to `settings.threads` worker threads, returning them in
+/// `paths` order. A file still being read after `settings.timeout` is reported as timed out and
+/// its worker replaced, so a read hanging on an unresponsive file server delays the scan by the
+/// timeout instead of blocking it. Once as many workers hang as the pool started with, the files
+/// no worker has picked up yet are given up too. Icons are added to `icons`.
+pub fn load_files(paths: Vec<PathBuf>, varo_path: &Path, cache: &NodeCache, icons: &Arc<IconStore>, settings: &LoadSettings) -> Vec<(PathBuf, FileLoad)> {
+    let jobs: Arc<Vec<Job>> = Arc::new(paths.into_iter()
+        .map(|path| {
+            let cached = cache.lookup(&path, varo_path);
+            Job { path, cached }
+        })
+        .collect());
+    let count = jobs.len();
+    let varo_path = Arc::new(varo_path.to_path_buf());
+    let next = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers = settings.threads.max(1).min(count);
+    let spawned = (0..workers).filter(|_| spawn_worker(&jobs, &varo_path, icons, &next, &sender)).count();
+    if spawned == 0 {
+        // No threads available; load on this one rather than not at all
+        return jobs.iter()
+            .map(|job| (job.path.clone(), FileLoad::load(&job.path, &varo_path, job.cached.clone(), icons)))
+            .collect();
+    }
+
+    let mut results: Vec<Option<FileLoad>> = (0..count).map(|_| None).collect();
+    let mut started: BTreeMap<usize, Instant> = BTreeMap::new();
+    let mut remaining = count;
+    let mut hung = 0;
+    while remaining > 0 {
+        let now = Instant::now();
+        let wait = started.values()
+            .map(|at| (*at + settings.timeout).saturating_duration_since(now))
+            .min()
+            .unwrap_or(settings.timeout);
+
+        match receiver.recv_timeout(wait) {
+            Ok(WorkerMessage::Started(index)) => {
+                started.insert(index, Instant::now());
+            }
+            Ok(WorkerMessage::Done(index, load)) => {
+                started.remove(&index);
+                // A file that already timed out keeps its timeout result
+                if results[index].is_none() {
+                    results[index] = Some(*load);
+                    remaining -= 1;
+                }
+            }
+            // The sender held here keeps the channel open, so only timeouts end up here
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {
+                let now = Instant::now();
+                let expired: Vec<usize> = started.iter()
+                    .filter(|(_, at)| now.duration_since(**at) >= settings.timeout)
+                    .map(|(index, _)| *index)
+                    .collect();
+                for index in expired {
+                    started.remove(&index);
+                    let job = &jobs[index];
+                    varo_log!("[Node Manager] Warning: Timed out reading {}", job.path.display());
+                    results[index] = Some(FileLoad::timed_out(&job.path, job.cached.clone(), settings.timeout));
+                    remaining -= 1;
+                    hung += 1;
+                    if hung < spawned {
+                        spawn_worker(&jobs, &varo_path, icons, &next, &sender);
+                    }
+                }
+
+                if hung >= spawned {
+                    // Every worker got stuck, so the folder is most likely unreachable
+                    let first_unclaimed = next.swap(count, Ordering::SeqCst).min(count);
+                    for (job, result) in jobs.iter().zip(results.iter_mut()).skip(first_unclaimed) {
+                        *result = Some(FileLoad::timed_out(&job.path, job.cached.clone(), settings.timeout));
+                        remaining -= 1;
+                    }
+                    if first_unclaimed < count {
+                        varo_log!("[Node Manager] Warning: Gave up on {} node files after {} reads timed out", count - first_unclaimed, hung);
+                    }
+                }
+            }
+        }
+    }
+
+    jobs.iter()
+        .zip(results)
+        .map(|(job, load)| (job.path.clone(), load.expect("every file is loaded or timed out")))
+        .collect()
+}
+
+/// Start a worker that loads jobs until none are left. A worker stuck on a hanging read is
+/// abandoned; if the read ever returns it carries on with the jobs left, if any. Returns false
+/// when no thread could be started.
+fn spawn_worker(jobs: &Arc<Vec<Job>>, varo_path: &Arc<PathBuf>, icons: &Arc<IconStore>, next: &Arc<AtomicUsize>, sender: &Sender<WorkerMessage>) -> bool {
+    let (jobs, varo_path, icons, next, sender) = (jobs.clone(), varo_path.clone(), icons.clone(), next.clone(), sender.clone());
+    let result = std::thread::Builder::new()
+        .name("varo-node-loader".to_string())
+        .spawn(move || loop {
+            let index = next.fetch_add(1, Ordering::SeqCst);
+            let job = match jobs.get(index) {
+                Some(job) => job,
+                None => break,
+            };
+            if sender.send(WorkerMessage::Started(index)).is_err() {
+                break;
+            }
+            let load = FileLoad::load(&job.path, &varo_path, job.cached.clone(), &icons);
+            if sender.send(WorkerMessage::Done(index, Box::new(load))).is_err() {
+                break;
+            }
+        });
+    match result {
+        Ok(_) => true,
+        Err(e) => {
+            varo_log!("[Node Manager] Warning: Failed to start a loader thread: {}", e);
+            false
+        }
+    }
+}
\ No newline at end of file
@@ -1,241 +1,713 @@
-use std::collections::HashMap;
-use crate::models::entities::{VaroNode, EnvPreset};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::models::entities::{VaroNode, Command, EnvPreset, EnvOrigin, LaunchPlan, LaunchState, NodeAccessReport, PlannedCommand, PlannedEnvVar, ScanStats};
+use crate::models::diagnostics::Diagnostic;
 use crate::models::errors::{VaroError, VaroResult};
-use crate::utils::node::load_nodes_in_dir;
-use crate::utils::commands::execute_program;
+use crate::utils::node::is_node_file_name;
+use crate::utils::commands::build_command;
+use crate::utils::duplicates::remove_duplicate_ids;
 use crate::utils::platform;
-use crate::utils::env::{get_current_env_vars, expand_tokens_with_map, get_env_vars_with_preset};
+use crate::utils::program::{resolve_program_path, PathType, ProgramSearch};
+use crate::core::nodes::access::AccessPolicy;
+use crate::core::nodes::cache::{NodeCache, NodeFile};
+use crate::core::nodes::icons::IconStore;
+use crate::core::nodes::loader::{load_files, LoadSettings, NodeScan, ScannedNodes};
+use crate::core::processes::ProcessRegistry;
+use crate::core::system::SystemInfo;
+use crate::utils::env::{get_current_env_vars, expand_template, get_env_vars_with_presets, apply_env_vars, diff_env_against_system, check_env_references};
+use crate::utils::expand::{ExpandContext, ExpansionIssue};
+use crate::utils::export::ExportVar;
+use crate::utils::icon::Icon;
+use crate::utils::log::varo_log;
 
 #[derive(Debug)]
 pub struct NodeManager {
     nodes: HashMap<String, VaroNode>,
+    /// Loaded node files in path order, kept so changed files can be reloaded on their own
+    files: BTreeMap<PathBuf, NodeFile>,
+    /// Node files from earlier scans, reused when they have not changed
+    cache: NodeCache,
+    /// Icons of the loaded and cached nodes, by content hash
+    icons: Arc<IconStore>,
+    scan_stats: ScanStats,
+    load_settings: LoadSettings,
+    /// VARO_PATH the current nodes were loaded from
+    varo_path: Option<String>,
+    access_policy: AccessPolicy,
+    varo_path_override: Option<String>,
+    varo_path_fallback: bool,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl NodeManager {
-    pub fn new() -> Self {
+    pub fn new(system_info: &SystemInfo) -> Self {
         Self {
             nodes: HashMap::new(),
+            files: BTreeMap::new(),
+            cache: NodeCache::default(),
+            icons: Arc::new(IconStore::default()),
+            scan_stats: ScanStats::default(),
+            load_settings: LoadSettings::default(),
+            varo_path: None,
+            access_policy: AccessPolicy::new(system_info),
+            varo_path_override: None,
+            varo_path_fallback: true,
+            diagnostics: Vec::new(),
         }
     }
 
+    /// A manager with the same settings and no nodes, to load a catalog into without holding this one's lock
+    pub fn detached(&self) -> Self {
+        Self {
+            nodes: HashMap::new(),
+            files: BTreeMap::new(),
+            cache: NodeCache::default(),
+            icons: Arc::new(IconStore::default()),
+            scan_stats: ScanStats::default(),
+            load_settings: self.load_settings.clone(),
+            varo_path: None,
+            access_policy: self.access_policy.clone(),
+            varo_path_override: self.varo_path_override.clone(),
+            varo_path_fallback: self.varo_path_fallback,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Show nodes saved by an earlier session, loaded from `varo_path`, until the nodes folder is scanned
+    pub fn use_cached_nodes(&mut self, varo_path: String, nodes: Vec<VaroNode>, icons: Vec<Icon>) {
+        self.clear_catalog(Some(varo_path));
+        for icon in icons {
+            self.icons.insert(icon);
+        }
+        self.diagnostics.clear();
+        self.scan_stats = ScanStats::default();
+        self.nodes = nodes.into_iter().map(|node| (node.id.clone(), node)).collect();
+        varo_log!("[Node Manager] Using {} cached nodes", self.nodes.len());
+    }
+
+    /// Every loaded node, including those hidden from the current user, sorted by id
+    pub fn get_loaded_nodes(&self) -> Vec<&VaroNode> {
+        let mut nodes: Vec<&VaroNode> = self.nodes.values().collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        nodes
+    }
+
+    /// VARO_PATH the current nodes were loaded from
+    pub fn get_varo_path(&self) -> Option<&str> {
+        self.varo_path.as_deref()
+    }
+
+    /// Problems found during the last node scan
+    pub fn get_diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Timing and cache statistics of the last full node scan
+    pub fn get_scan_stats(&self) -> &ScanStats {
+        &self.scan_stats
+    }
+
+    /// Forget cached node files, so the next scan reads and parses every file again
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+        self.icons.forget_files();
+    }
+
+    /// The icon with `key`, scaled down to fit `size` pixels when given. See [`IconStore::get`].
+    pub fn get_icon(&self, key: &str, size: Option<u32>) -> Option<Arc<Icon>> {
+        self.icons.get(key, size)
+    }
+
+    /// Icons of the loaded nodes, each once
+    pub fn get_loaded_icons(&self) -> Vec<Arc<Icon>> {
+        let keys: BTreeSet<&str> = self.nodes.values().map(|node| node.icon.as_str()).collect();
+        keys.into_iter().filter_map(|key| self.icons.get(key, None)).collect()
+    }
+
+    /// Use a fixed VARO_PATH instead of the one from the preset or system environment
+    pub fn set_varo_path_override(&mut self, varo_path: Option<String>) {
+        self.varo_path_override = varo_path;
+    }
+
+    /// Apply the `access` config section (group definitions used by node allow/deny rules)
+    pub fn set_access_config(&mut self, access_config: &serde_json::Value) {
+        self.access_policy.set_config_groups(access_config);
+    }
+
+    /// Apply settings from the `nodes` config section
+    pub fn set_nodes_config(&mut self, nodes_config: &serde_json::Value) {
+        self.varo_path_fallback = nodes_config.get("varo_path_fallback")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+        self.load_settings = LoadSettings::from_config(nodes_config);
+    }
+
     pub fn load_nodes_from_varo_path(&mut self) -> VaroResult<()> {
-        self.load_nodes_from_varo_path_with_preset(None)
+        self.load_nodes_from_varo_path_with_presets(&[])
     }
 
-    pub fn load_nodes_from_varo_path_with_preset(&mut self, preset: Option<&EnvPreset>) -> VaroResult<()> {
-        // Get environment variables with preset precedence
-        let env_map = get_env_vars_with_preset(preset);
-        
-        if let Some(preset) = preset {
-            println!("[Node Manager] Using preset '{}' for environment variables", preset.name);
+    /// Resolve the VARO_PATH nodes are loaded from: the override if set,
+    /// otherwise the value from the active presets (the top-most layer that sets it wins)
+    /// or the system environment
+    pub fn resolve_varo_path(&self, presets: &[EnvPreset]) -> Option<String> {
+        if let Some(varo_path) = &self.varo_path_override {
+            return Some(varo_path.clone());
+        }
+        get_env_vars_with_presets(presets).remove("VARO_PATH")
+    }
+
+    /// Load nodes from the VARO_PATH of the given preset stack, bottom layer first
+    pub fn load_nodes_from_varo_path_with_presets(&mut self, presets: &[EnvPreset]) -> VaroResult<()> {
+        let scanned = self.prepare_scan(presets).run();
+        self.install_scan(scanned, presets);
+        Ok(())
+    }
+
+    /// Prepare a scan of the VARO_PATH of the given preset stack. Running it reads the node files
+    /// without this manager, so callers sharing it can release their lock until
+    /// [`NodeManager::install_scan`].
+    pub fn prepare_scan(&self, presets: &[EnvPreset]) -> NodeScan {
+        if presets.is_empty() {
+            varo_log!("[Node Manager] Using system environment variables only");
         } else {
-            println!("[Node Manager] Using system environment variables only");
+            let names: Vec<&str> = presets.iter().map(|p| p.name.as_str()).collect();
+            varo_log!("[Node Manager] Using presets {:?} for environment variables", names);
         }
-        
+        NodeScan::new(self.resolve_varo_path(presets), self.cache.clone(), self.icons.clone(), self.load_settings.clone())
+    }
+
+    /// Replace the loaded nodes with the result of a scan of the same preset stack. Readers never
+    /// see a partly loaded catalog: the node map is swapped in once every file has been read.
+    pub fn install_scan(&mut self, scanned: ScannedNodes, presets: &[EnvPreset]) {
+        self.diagnostics.clear();
+        let mut stats = scanned.stats;
+
         // Check if VARO_PATH is available in the combined environment
-        let varo_path = match env_map.get("VARO_PATH") {
+        let varo_path = match scanned.varo_path {
             Some(path) => path,
             None => {
                 // Clear nodes and log warning but don't fail
-                self.nodes.clear();
-                if let Some(preset) = preset {
-                    println!("[Node Manager] Warning: Preset '{}' does not define VARO_PATH and no system VARO_PATH found. No nodes loaded.", preset.name);
-                } else {
-                    println!("[Node Manager] Warning: VARO_PATH environment variable not set. No nodes loaded.");
-                }
-                return Ok(());
+                self.clear_catalog(None);
+                self.scan_stats = stats;
+                let message = match presets {
+                    [] => "VARO_PATH environment variable not set. No nodes loaded.".to_string(),
+                    [preset] => format!("Preset '{}' does not define VARO_PATH and no system VARO_PATH found. No nodes loaded.", preset.name),
+                    _ => format!(
+                        "None of the active presets ({}) define VARO_PATH and no system VARO_PATH found. No nodes loaded.",
+                        presets.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(", ")
+                    ),
+                };
+                varo_log!("[Node Manager] Warning: {}", message);
+                self.diagnostics.push(Diagnostic::warning(presets.last().and_then(|p| p.filepath.as_deref()), message));
+                return;
             }
         };
-            
+
+        // Don't fail if the directory doesn't exist
         let nodes_path = format!("{}/nodes", varo_path);
-        println!("[Node Manager] Loading nodes from: {}", nodes_path);
-        
-        // Try to load nodes, but don't fail if the directory doesn't exist
-        let nodes = match load_nodes_in_dir(&nodes_path) {
-            Ok(nodes) => nodes,
+        let files = match scanned.files {
+            Ok(files) => files,
             Err(e) => {
                 // Clear nodes and log warning but don't fail
-                self.nodes.clear();
-                println!("[Node Manager] Warning: Failed to load nodes from {}: {}. No nodes loaded.", nodes_path, e);
-                return Ok(());
+                self.clear_catalog(Some(varo_path));
+                varo_log!("[Node Manager] Warning: Failed to load nodes from {}: {}. No nodes loaded.", nodes_path, e);
+                stats.error = Some(e.clone());
+                self.scan_stats = stats;
+                self.diagnostics.push(Diagnostic::error(Some(&nodes_path), e));
+                return;
             }
         };
-        
-        println!("[Node Manager] Loaded {} nodes from disk", nodes.len());
-        
+
+        // Unchanged files came from the cache; only new and edited ones were parsed
+        self.files = files.into_iter()
+            .map(|(path, load)| {
+                let file = self.cache.record(path.clone(), load, &mut stats);
+                (path, file)
+            })
+            .collect();
+        let files = &self.files;
+        self.cache.prune(Path::new(&varo_path), |path| files.contains_key(path));
+        self.varo_path = Some(varo_path);
+        self.rebuild_catalog(presets);
+        stats.total_ms = scanned.started.elapsed().as_millis() as u64;
+        self.scan_stats = stats;
+
+        let stats = &self.scan_stats;
+        varo_log!(
+            "[Node Manager] Node loading complete. Total nodes in memory: {} ({} files: {} reused, {} unchanged, {} parsed, {} timed out; list {} ms, load {} ms, total {} ms)",
+            self.nodes.len(), stats.files, stats.reused, stats.unchanged, stats.parsed, stats.timed_out, stats.list_ms, stats.load_ms, stats.total_ms
+        );
+    }
+
+    /// Reload only the node files among `paths`, plus the nodes using a changed icon, and rebuild
+    /// the catalog. Files that no longer exist are dropped. Paths outside `VARO_PATH/nodes` and
+    /// `VARO_PATH/icons` are ignored. Returns whether anything was reloaded.
+    pub fn apply_file_changes(&mut self, paths: &[PathBuf], presets: &[EnvPreset]) -> bool {
+        let (varo_path, nodes_dir) = match (self.varo_path.clone(), self.get_nodes_dir()) {
+            (Some(varo_path), Some(dir)) => (varo_path, dir),
+            _ => return false,
+        };
+        let icons_dir = self.get_icons_dir();
+
+        let mut reload: BTreeSet<PathBuf> = BTreeSet::new();
+        for path in paths {
+            if path.parent() == Some(nodes_dir.as_path()) && is_node_file_name(path) {
+                reload.insert(path.clone());
+            } else if icons_dir.as_ref().is_some_and(|dir| path.starts_with(dir)) {
+                // Nodes showing this icon, and nodes whose icon failed to load since it may have just appeared
+                for (file, entry) in &self.files {
+                    let uses_icon = entry.node.as_ref().is_some_and(|node| {
+                        node.icon.is_empty() || node.icon_file.as_deref().map(Path::new) == Some(path.as_path())
+                    });
+                    if uses_icon {
+                        reload.insert(file.clone());
+                    }
+                }
+            }
+        }
+        if reload.is_empty() {
+            return false;
+        }
+
+        // The watcher saw these change, so they are parsed again even if their timestamps look the
+        // same. Statistics describe full scans, so the counts from single files are dropped.
+        let mut present = Vec::new();
+        for path in reload {
+            self.cache.remove(&path);
+            if path.is_file() {
+                varo_log!("[Node Manager] Reloading node file: {}", path.display());
+                present.push(path);
+            } else if self.files.remove(&path).is_some() {
+                varo_log!("[Node Manager] Node file removed: {}", path.display());
+            }
+        }
+        let mut stats = ScanStats::default();
+        for (path, load) in load_files(present, Path::new(&varo_path), &self.cache, &self.icons, &self.load_settings) {
+            let file = self.cache.record(path.clone(), load, &mut stats);
+            self.files.insert(path, file);
+        }
+        self.rebuild_catalog(presets);
+        true
+    }
+
+    /// Folder node files are loaded from, when `VARO_PATH` is set
+    pub fn get_nodes_dir(&self) -> Option<PathBuf> {
+        self.varo_path.as_ref().map(|varo_path| PathBuf::from(format!("{}/nodes", varo_path)))
+    }
+
+    /// Folder relative node icons are loaded from, when `VARO_PATH` is set
+    pub fn get_icons_dir(&self) -> Option<PathBuf> {
+        self.varo_path.as_ref().map(|varo_path| PathBuf::from(format!("{}/icons", varo_path)))
+    }
+
+    fn clear_catalog(&mut self, varo_path: Option<String>) {
         self.nodes.clear();
+        self.files.clear();
+        self.varo_path = varo_path;
+    }
+
+    /// Rebuild the node map from the loaded files: the first file in path order wins for each id,
+    /// and node env references are checked against the preset env
+    fn rebuild_catalog(&mut self, presets: &[EnvPreset]) {
+        let mut diagnostics: Vec<Diagnostic> = self.files.values()
+            .flat_map(|file| file.diagnostics.iter().cloned())
+            .collect();
+        let mut nodes: Vec<VaroNode> = self.files.values()
+            .filter_map(|file| file.node.clone())
+            .collect();
+
+        // Files are read in path order, so the first definition of an id wins
+        remove_duplicate_ids(&mut nodes, "node", |n| (n.id.as_str(), n.filepath.as_deref()), &mut diagnostics);
+
+        // Node env can reference anything the presets define, so check references against the preset env
+        let mut base_env = get_env_vars_with_presets(presets);
+        if let Some(varo_path) = &self.varo_path {
+            base_env.insert("VARO_PATH".to_string(), varo_path.clone());
+        }
         for node in &nodes {
-            println!("[Node Manager]   - Loading node: {} ({})", node.name, node.id);
-            self.nodes.insert(node.id.clone(), node.clone());
+            let context = self.expand_context(node, presets);
+            check_env_references(&node.env, &base_env, &context, node.filepath.as_deref(), &mut diagnostics);
         }
         
-        println!("[Node Manager] Node loading complete. Total nodes in memory: {}", self.nodes.len());
-        Ok(())
+        varo_log!("[Node Manager] Loaded {} nodes ({} diagnostics)", nodes.len(), diagnostics.len());
+        
+        self.diagnostics = diagnostics;
+        self.nodes.clear();
+        for node in nodes {
+            varo_log!("[Node Manager]   - Loading node: {} ({})", node.name, node.id);
+            self.nodes.insert(node.id.clone(), node);
+        }
+
+        // Keep the icons of cached files too, so switching back to another VARO_PATH finds them
+        let used: HashSet<&str> = self.cache.icon_keys()
+            .chain(self.nodes.values().map(|node| node.icon.as_str()))
+            .collect();
+        self.icons.retain(&used);
     }
 
+    /// Returns the nodes the current user may see on this platform, sorted by name
     pub fn get_all_nodes(&self) -> Vec<&VaroNode> {
-        let mut nodes: Vec<&VaroNode> = self.nodes.values().collect();
+        let mut nodes: Vec<&VaroNode> = self.nodes.values()
+            .filter(|node| self.access_policy.is_allowed(node))
+            .collect();
         nodes.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
         nodes
     }
 
+    /// Returns access reports for every loaded node hidden from the current user
+    pub fn get_hidden_nodes(&self) -> Vec<NodeAccessReport> {
+        let mut reports: Vec<NodeAccessReport> = self.nodes.values()
+            .map(|node| self.access_policy.explain(node))
+            .filter(|report| !report.allowed)
+            .collect();
+        reports.sort_by(|a, b| a.node_name.to_lowercase().cmp(&b.node_name.to_lowercase()));
+        reports
+    }
+
+    /// Explains whether a node is visible to the current user and why
+    pub fn explain_node_access(&self, id: &str) -> VaroResult<NodeAccessReport> {
+        let node = self.nodes.get(id)
+            .ok_or_else(|| VaroError::node(format!("Node not found: {}", id)))?;
+        Ok(self.access_policy.explain(node))
+    }
+
     pub fn get_node(&self, id: &str) -> Option<&VaroNode> {
         self.nodes.get(id)
     }
 
-    pub fn execute_node(&self, id: &str) -> VaroResult<()> {
-        self.execute_node_with_env_expansion(id, None)
+    pub fn execute_node(&self, id: &str, registry: &ProcessRegistry) -> VaroResult<()> {
+        self.execute_node_with_env_expansion(id, &[], registry)
     }
 
-    pub fn execute_node_with_env_expansion(&self, id: &str, selected_preset: Option<&EnvPreset>) -> VaroResult<()> {
-        println!("[Node Manager] Starting execution for node ID: {}", id);
-        
+    /// Look up a node the current user is allowed to launch
+    fn get_launchable_node(&self, id: &str) -> VaroResult<&VaroNode> {
         let node = self.nodes.get(id)
             .ok_or_else(|| VaroError::node(format!("Node not found: {}", id)))?;
 
-        println!("[Node Manager] Found node: {}", node.name);
-        println!("[Node Manager] Node has {} commands", node.commands.len());
+        varo_log!("[Node Manager] Found node: {}", node.name);
 
-        if node.commands.is_empty() {
-            println!("[Node Manager] Error: Node has no commands to execute");
-            return Err(VaroError::execution("Node has no commands to execute"));
+        let access_report = self.access_policy.explain(node);
+        if !access_report.allowed {
+            varo_log!("[Node Manager] Access denied for node {}: {:?}", id, access_report.reasons);
+            return Err(VaroError::access_denied(format!(
+                "Node '{}' is not available: {}",
+                node.name,
+                access_report.reasons.join("; ")
+            )));
         }
 
-        // Build environment variable map for expansion (used for ${VAR} substitution in paths/args)
-        // This includes: system vars + preset vars + node vars (in priority order)
-        let mut expansion_env = get_current_env_vars();
-        println!("[Node Manager] Starting with {} system environment variables", expansion_env.len());
-        
-        // Add selected preset environment variables if available
-        if let Some(preset) = selected_preset {
-            println!("[Node Manager] Adding environment variables from selected preset: {}", preset.name);
-            for env_var in &preset.env {
-                println!("[Node Manager]   Adding preset env: {}={}", env_var.name, env_var.value);
-                expansion_env.insert(env_var.name.clone(), env_var.value.clone());
-            }
-        }
+        Ok(node)
+    }
 
-        // Add node-specific environment variables
-        for env_var in &node.env {
-            println!("[Node Manager]   Adding node env: {}={}", env_var.name, env_var.value);
-            expansion_env.insert(env_var.name.clone(), env_var.value.clone());
+    /// Values for built-in tokens such as `${node.id}` when expanding a node's env and commands
+    /// `${preset.id}` is the top-most active preset.
+    fn expand_context(&self, node: &VaroNode, presets: &[EnvPreset]) -> ExpandContext {
+        ExpandContext {
+            varo_path: self.varo_path_override.clone(),
+            node_dir: node.filepath.as_deref()
+                .and_then(|f| Path::new(f).parent())
+                .map(|dir| dir.to_string_lossy().to_string()),
+            node_id: Some(node.id.clone()),
+            preset_id: presets.last().map(|p| p.id.clone()),
         }
+    }
 
-        // Build environment variables to pass to execute_program (separate from expansion map)
-        // These are the actual env vars that will be set in the child process
-        // This includes both preset and node environment variables (expanded)
-        let mut env_vars_for_execution = HashMap::new();
-        
-        // Add preset environment variables (expanded)
-        if let Some(preset) = selected_preset {
-            println!("[Node Manager]   Adding expanded preset environment variables:");
-            for env_var in &preset.env {
-                let expanded_value = expand_tokens_with_map(&env_var.value, &expansion_env);
-                println!("[Node Manager]     preset: {}={}", env_var.name, expanded_value);
-                env_vars_for_execution.insert(env_var.name.clone(), expanded_value);
+    /// Build the environment a node's commands run with: the system env with each active preset
+    /// and then node operations applied in dependency order, plus the VARO_PATH override
+    fn build_launch_env(&self, node: &VaroNode, presets: &[EnvPreset]) -> LaunchEnv {
+        let context = self.expand_context(node, presets);
+        let mut issues = Vec::new();
+        let system_env = get_current_env_vars();
+        let mut preset_env = system_env.clone();
+        varo_log!("[Node Manager] Starting with {} system environment variables", preset_env.len());
+
+        // Apply active presets bottom to top, remembering which layer last changed each variable
+        let mut preset_layer: HashMap<String, String> = HashMap::new();
+        for preset in presets {
+            varo_log!("[Node Manager] Applying environment variables from preset: {}", preset.name);
+            for env_var in &preset.flattened_env {
+                varo_log!("[Node Manager]   preset: {} {} {}", env_var.operation.as_deref().unwrap_or("set"), env_var.name, env_var.value);
+            }
+            let before = preset_env.clone();
+            let preset_context = ExpandContext {
+                node_dir: None,
+                node_id: None,
+                preset_id: Some(preset.id.clone()),
+                ..context.clone()
+            };
+            issues.extend(apply_env_vars(&mut preset_env, &preset.flattened_env, &preset_context));
+            for env_var in &preset.flattened_env {
+                if before.get(&env_var.name) != preset_env.get(&env_var.name) {
+                    preset_layer.insert(env_var.name.clone(), preset.id.clone());
+                }
             }
         }
-        
-        // Add node environment variables (expanded) - these override preset vars if there's a conflict
+
+        // Apply node-specific environment variables - these take effect after preset vars
+        let mut expansion_env = preset_env.clone();
         if !node.env.is_empty() {
-            println!("[Node Manager]   Adding expanded node environment variables:");
+            varo_log!("[Node Manager] Applying node environment variables:");
             for env_var in &node.env {
-                let expanded_value = expand_tokens_with_map(&env_var.value, &expansion_env);
-                println!("[Node Manager]     node: {}={}", env_var.name, expanded_value);
-                env_vars_for_execution.insert(env_var.name.clone(), expanded_value);
+                varo_log!("[Node Manager]   node: {} {} {}", env_var.operation.as_deref().unwrap_or("set"), env_var.name, env_var.value);
             }
+            issues.extend(apply_env_vars(&mut expansion_env, &node.env, &context));
         }
-        
-        let env_vars = if env_vars_for_execution.is_empty() {
-            println!("[Node Manager]   Final environment variables: None");
+
+        if let Some(varo_path) = &self.varo_path_override {
+            expansion_env.insert("VARO_PATH".to_string(), varo_path.clone());
+        }
+
+        // Only the vars the preset and node changed or removed relative to the system env
+        // are passed to the launched programs
+        let (changed, removed) = diff_env_against_system(&expansion_env, &system_env);
+
+        let origin_of = |name: &str| {
+            if name == "VARO_PATH" && self.varo_path_override.is_some() {
+                EnvOrigin::Override
+            } else if preset_env.get(name) == expansion_env.get(name) {
+                EnvOrigin::Preset
+            } else {
+                EnvOrigin::Node
+            }
+        };
+        let planned_var = |name: &String, value: Option<&String>| {
+            let origin = origin_of(name);
+            PlannedEnvVar {
+                name: name.clone(),
+                value: value.cloned(),
+                system_value: system_env.get(name).cloned(),
+                preset_id: match origin {
+                    EnvOrigin::Preset => preset_layer.get(name).cloned(),
+                    _ => None,
+                },
+                origin,
+            }
+        };
+        let mut changes: Vec<PlannedEnvVar> = changed.iter()
+            .map(|(name, value)| planned_var(name, Some(value)))
+            .chain(removed.iter().map(|name| planned_var(name, None)))
+            .collect();
+        changes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let env_vars = if changed.is_empty() {
+            varo_log!("[Node Manager]   Final environment variables: None");
             None
         } else {
-            println!("[Node Manager]   Final environment variables ({} total):", env_vars_for_execution.len());
-            for (key, value) in &env_vars_for_execution {
-                println!("[Node Manager]     {}={}", key, value);
+            varo_log!("[Node Manager]   Final environment variables ({} total):", changed.len());
+            for (key, value) in &changed {
+                varo_log!("[Node Manager]     {}={}", key, value);
             }
-            Some(env_vars_for_execution.clone())
+            Some(changed)
         };
 
-        // Execute all commands in sequence
-        println!("[Node Manager] Executing {} commands in sequence", node.commands.len());
-        for (index, command) in node.commands.iter().enumerate() {
-            println!("[Node Manager] Executing command {}/{}", index + 1, node.commands.len());
-            println!("[Node Manager]   Original Path: {}", command.path);
-            println!("[Node Manager]   Original Args: '{}'", command.args);
-            println!("[Node Manager]   Path type: {}", command.path_type);
-            println!("[Node Manager]   Wait: {}", command.wait);
-
-            // Expand environment variables in path and args
-            let expanded_path = expand_tokens_with_map(&command.path, &expansion_env);
-            let expanded_args_str = expand_tokens_with_map(&command.args, &expansion_env);
-            
-            println!("[Node Manager]   Expanded Path: {}", expanded_path);
-            println!("[Node Manager]   Expanded Args: '{}'", expanded_args_str);
-            
-            let args = if expanded_args_str.is_empty() {
-                println!("[Node Manager]   Parsed args: None (empty)");
-                None
-            } else {
-                let parsed_args: Vec<String> = expanded_args_str.split_whitespace().map(|s| s.to_string()).collect();
-                println!("[Node Manager]   Parsed args: {:?}", parsed_args);
-                Some(parsed_args)
-            };
+        let unset_vars = if removed.is_empty() {
+            None
+        } else {
+            varo_log!("[Node Manager]   Unset environment variables: {:?}", removed);
+            Some(removed)
+        };
 
-            // Determine if we should wait for this command
-            let wait_for_completion = command.wait;
-            println!("[Node Manager]   Wait for completion: {}", wait_for_completion);
+        // Failed `${VAR:?message}` tokens stop the launch; other problems were reported when loading
+        let mut errors = Vec::new();
+        for issue in issues {
+            varo_log!("[Node Manager]   Env issue: {}", issue.message());
+            if let ExpansionIssue::Failed { .. } = issue {
+                errors.push(issue.message());
+            }
+        }
 
-            // Handle different path types
-            let result = match command.path_type.to_lowercase().as_str() {
-                "url" => {
-                    println!("[Node Manager] Detected URL path type, opening in browser");
-                    if !platform::open_url_in_browser(&expanded_path) {
-                        Err(VaroError::execution("Failed to open URL in browser"))
-                    } else {
-                        println!("[Node Manager] URL opened successfully");
-                        Ok(())
-                    }
-                },
-                _ => {
-                    // Handle executable paths (rel, abs, or default)
-                    println!("[Node Manager] Calling execute_program with:");
-                    println!("[Node Manager]   path: {}", expanded_path);
-                    println!("[Node Manager]   args: {:?}", args);
-                    println!("[Node Manager]   env_vars: {:?}", env_vars);
-                    println!("[Node Manager]   wait: {}", wait_for_completion);
-
-                    execute_program(
-                        expanded_path,
-                        args,
-                        env_vars.clone(),
-                        wait_for_completion
-                    ).map_err(|e| {
-                        println!("[Node Manager] Error from execute_program: {}", e);
-                        VaroError::execution(format!("Failed to execute command: {}", e))
-                    })
+        LaunchEnv {
+            expansion_env,
+            context,
+            changes,
+            env_vars,
+            unset_vars,
+            errors,
+            varo_path_fallback: self.varo_path_fallback,
+        }
+    }
+
+    /// Expand and resolve one command without running it.
+    /// Problems that would stop the launch are reported in `error`.
+    fn plan_command(node: &VaroNode, index: usize, command: &Command, launch_env: &LaunchEnv) -> PlannedCommand {
+        let env = &launch_env.expansion_env;
+        let mut unresolved: Vec<String> = Vec::new();
+        let mut errors: Vec<String> = Vec::new();
+        let mut expand = |input: &str| {
+            let expanded = expand_template(input, env, &launch_env.context);
+            for name in expanded.undefined {
+                if !unresolved.contains(&name) {
+                    unresolved.push(name);
                 }
-            };
+            }
+            errors.extend(expanded.errors);
+            expanded.value
+        };
 
-            // Handle execution result
-            match result {
-                Ok(()) => {
-                    println!("[Node Manager] Command {}/{} executed successfully", index + 1, node.commands.len());
-                },
-                Err(e) => {
-                    println!("[Node Manager] Command {}/{} failed: {}", index + 1, node.commands.len(), e);
-                    return Err(e);
+        // Expand environment variables in path and in each argument separately,
+        // so an expanded value containing spaces is never split again
+        let path = expand(&command.path);
+        let args: Vec<String> = command.args.iter().map(|arg| expand(arg)).collect();
+        let cwd = command.cwd.as_ref().or(node.cwd.as_ref()).map(|c| expand(c));
+
+        // Infer the type from the expanded path when not declared
+        let path_type = PathType::from_command(&command.path_type, &path);
+
+        let mut planned = PlannedCommand {
+            index,
+            path_type: path_type.as_str().to_string(),
+            path: path.clone(),
+            program: None,
+            args,
+            cwd: None,
+            wait: command.wait,
+            unresolved,
+            error: errors.into_iter().next(),
+        };
+
+        if planned.error.is_some() || path_type == PathType::Url {
+            planned.cwd = cwd;
+            return planned;
+        }
+
+        let node_dir = node.filepath.as_deref().and_then(|f| Path::new(f).parent());
+        let varo_path = if launch_env.varo_path_fallback {
+            env.get("VARO_PATH").map(|s| s.as_str())
+        } else {
+            None
+        };
+        let search = ProgramSearch::from_env(node_dir, varo_path, env);
+        match resolve_program_path(&path, path_type, &search) {
+            Ok(program) => planned.program = Some(program.to_string_lossy().to_string()),
+            Err(e) => planned.error = Some(e),
+        }
+
+        match resolve_working_dir(cwd.as_deref(), node) {
+            Ok(dir) => planned.cwd = dir.map(|d| d.to_string_lossy().to_string()),
+            Err(e) => {
+                planned.cwd = cwd;
+                planned.error.get_or_insert(e);
+            }
+        }
+
+        planned
+    }
+
+    /// Work out what launching a node would run: resolved programs, arguments,
+    /// working directories and environment changes. Nothing is started.
+    pub fn plan_node_launch(&self, id: &str, presets: &[EnvPreset]) -> VaroResult<LaunchPlan> {
+        let node = self.get_launchable_node(id)?;
+        let launch_env = self.build_launch_env(node, presets);
+
+        let commands = node.commands.iter()
+            .enumerate()
+            .map(|(index, command)| Self::plan_command(node, index, command, &launch_env))
+            .collect();
+
+        Ok(LaunchPlan {
+            node_id: node.id.clone(),
+            node_name: node.name.clone(),
+            preset_ids: presets.iter().map(|p| p.id.clone()).collect(),
+            env: launch_env.changes,
+            errors: launch_env.errors,
+            commands,
+        })
+    }
+
+    /// Resolve the variables the active presets, and optionally a node, change relative to the
+    /// system environment, for writing to an activation script.
+    /// Without a node this is the preset environment nodes are loaded with, plus the VARO_PATH override.
+    pub fn export_env(&self, node_id: Option<&str>, presets: &[EnvPreset]) -> VaroResult<Vec<ExportVar>> {
+        let node = node_id.map(|id| self.get_launchable_node(id)).transpose()?;
+        let system_env = get_current_env_vars();
+
+        let changes: Vec<(String, Option<String>)> = match node {
+            Some(node) => {
+                let launch_env = self.build_launch_env(node, presets);
+                if !launch_env.errors.is_empty() {
+                    return Err(VaroError::env_preset(launch_env.errors.join("; ")));
+                }
+                launch_env.changes.into_iter().map(|var| (var.name, var.value)).collect()
+            }
+            None => {
+                let mut env = get_env_vars_with_presets(presets);
+                if let Some(varo_path) = &self.varo_path_override {
+                    env.insert("VARO_PATH".to_string(), varo_path.clone());
                 }
+                let (changed, removed) = diff_env_against_system(&env, &system_env);
+                let mut changes: Vec<(String, Option<String>)> = changed.into_iter()
+                    .map(|(name, value)| (name, Some(value)))
+                    .chain(removed.into_iter().map(|name| (name, None)))
+                    .collect();
+                changes.sort_by(|a, b| a.0.cmp(&b.0));
+                changes
+            }
+        };
+
+        // Variables built with append/prepend extend the value of the shell the script runs in
+        let node_env = node.map_or(&[][..], |n| n.env.as_slice());
+        let extended: Vec<&str> = presets.iter()
+            .flat_map(|p| p.flattened_env.iter())
+            .chain(node_env)
+            .filter(|var| matches!(var.operation.as_deref().map(str::to_lowercase).as_deref(), Some("append" | "prepend")))
+            .map(|var| var.name.as_str())
+            .collect();
+
+        Ok(changes.into_iter()
+            .map(|(name, value)| ExportVar {
+                base: if extended.contains(&name.as_str()) { system_env.get(&name).cloned() } else { None },
+                name,
+                value,
+            })
+            .collect())
+    }
+
+    /// Execute a node's commands in order, tracking each started program in `registry`.
+    /// Commands with `wait` block until their program exits.
+    pub fn execute_node_with_env_expansion(&self, id: &str, presets: &[EnvPreset], registry: &ProcessRegistry) -> VaroResult<()> {
+        self.prepare_launch(id, presets)?.run(registry)
+    }
+
+    /// Check that a node can be launched: the user may run it, its environment resolves and its
+    /// first command can be started. The returned launch runs without the manager.
+    pub fn prepare_launch(&self, id: &str, presets: &[EnvPreset]) -> VaroResult<NodeLaunch> {
+        varo_log!("[Node Manager] Starting execution for node ID: {}", id);
+
+        let node = self.get_launchable_node(id)?;
+
+        varo_log!("[Node Manager] Node has {} commands", node.commands.len());
+
+        let first_command = match node.commands.first() {
+            Some(command) => command,
+            None => {
+                varo_log!("[Node Manager] Error: Node has no commands to execute");
+                return Err(VaroError::execution("Node has no commands to execute"));
             }
+        };
+
+        let launch_env = self.build_launch_env(node, presets);
+        if !launch_env.errors.is_empty() {
+            return Err(VaroError::execution(launch_env.errors.join("; ")));
         }
 
-        println!("[Node Manager] All commands executed successfully");
-        Ok(())
+        let first = Self::plan_command(node, 0, first_command, &launch_env);
+        if let Some(error) = &first.error {
+            varo_log!("[Node Manager] Command 1/{} cannot run: {}", node.commands.len(), error);
+            return Err(VaroError::execution(error.clone()));
+        }
+
+        Ok(NodeLaunch {
+            node: node.clone(),
+            preset_id: presets.last().map(|p| p.id.clone()),
+            launch_env,
+            first: Some(first),
+        })
     }
 
-    pub fn refresh_with_preset(&mut self, preset: &EnvPreset) -> VaroResult<()> {
-        // Load nodes using the preset's environment variables
-        self.load_nodes_from_varo_path_with_preset(Some(preset))
+    pub fn refresh_with_presets(&mut self, presets: &[EnvPreset]) -> VaroResult<()> {
+        // Load nodes using the active presets' environment variables
+        self.load_nodes_from_varo_path_with_presets(presets)
     }
 
     pub fn get_node_count(&self) -> usize {
@@ -243,15 +715,15 @@ impl NodeManager {
     }
 
     pub fn show_node_in_folder(&self, id: &str) -> VaroResult<()> {
-        // println!("[Node Manager] Attempting to show node in folder: {}", id);
+        // varo_log!("[Node Manager] Attempting to show node in folder: {}", id);
         
         let node = self.nodes.get(id)
             .ok_or_else(|| VaroError::node(format!("Node not found: {}", id)))?;
 
-        // println!("[Node Manager] Found node: {}", node.name);
+        // varo_log!("[Node Manager] Found node: {}", node.name);
 
         if let Some(filepath) = &node.filepath {
-            // println!("[Node Manager] Node filepath: {}", filepath);
+            // varo_log!("[Node Manager] Node filepath: {}", filepath);
             
             let file_path = std::path::Path::new(filepath);
 
@@ -260,10 +732,161 @@ impl NodeManager {
                 return Err(VaroError::execution("Failed to open file in folder"));
             }
         } else {
-            // println!("[Node Manager] Node has no filepath specified");
+            // varo_log!("[Node Manager] Node has no filepath specified");
             return Err(VaroError::execution("Node has no filepath specified"));
         }
 
         Ok(())
     }
+}
+
+/// A node launch checked by [`NodeManager::prepare_launch`]. It owns what it needs, so the
+/// commands can be started and waited for without holding the manager's lock.
+pub struct NodeLaunch {
+    node: VaroNode,
+    /// Top-most active preset, recorded with each launched program
+    preset_id: Option<String>,
+    launch_env: LaunchEnv,
+    /// The first command, already resolved
+    first: Option<PlannedCommand>,
+}
+
+impl NodeLaunch {
+    pub fn node_id(&self) -> &str {
+        &self.node.id
+    }
+
+    /// Run the commands in order, tracking each started program in `registry`.
+    /// Commands with `wait` block until their program exits.
+    pub fn run(mut self, registry: &ProcessRegistry) -> VaroResult<()> {
+        let node = &self.node;
+        let launch_env = &self.launch_env;
+
+        // Execute all commands in sequence. Each command is resolved right before it runs,
+        // so earlier commands can create what later ones need
+        varo_log!("[Node Manager] Executing {} commands in sequence", node.commands.len());
+        for (index, command) in node.commands.iter().enumerate() {
+            varo_log!("[Node Manager] Executing command {}/{}", index + 1, node.commands.len());
+            varo_log!("[Node Manager]   Original Path: {}", command.path);
+            varo_log!("[Node Manager]   Original Args: {:?}", command.args);
+            varo_log!("[Node Manager]   Path type: {}", command.path_type);
+
+            let planned = match self.first.take() {
+                Some(first) => first,
+                None => NodeManager::plan_command(node, index, command, launch_env),
+            };
+
+            varo_log!("[Node Manager]   Expanded Path: {}", planned.path);
+            varo_log!("[Node Manager]   Expanded Args: {:?}", planned.args);
+            varo_log!("[Node Manager]   Effective path type: {}", planned.path_type);
+            varo_log!("[Node Manager]   Wait for completion: {}", planned.wait);
+
+            let result = match (&planned.error, &planned.program) {
+                (Some(error), _) => Err(VaroError::execution(error.clone())),
+                (None, None) => {
+                    varo_log!("[Node Manager] Detected URL path type, opening in browser");
+                    if !platform::open_url_in_browser(&planned.path) {
+                        Err(VaroError::execution("Failed to open URL in browser"))
+                    } else {
+                        varo_log!("[Node Manager] URL opened successfully");
+                        Ok(())
+                    }
+                },
+                (None, Some(program)) => {
+                    let args = if planned.args.is_empty() { None } else { Some(planned.args.clone()) };
+
+                    varo_log!("[Node Manager] Launching through the process registry:");
+                    varo_log!("[Node Manager]   path: {}", program);
+                    varo_log!("[Node Manager]   args: {:?}", args);
+                    varo_log!("[Node Manager]   env_vars: {:?}", launch_env.env_vars);
+                    varo_log!("[Node Manager]   unset_vars: {:?}", launch_env.unset_vars);
+                    varo_log!("[Node Manager]   cwd: {:?}", planned.cwd);
+                    varo_log!("[Node Manager]   wait: {}", planned.wait);
+
+                    let cmd = build_command(
+                        program,
+                        args,
+                        launch_env.env_vars.clone(),
+                        launch_env.unset_vars.clone(),
+                        planned.cwd.as_deref().map(Path::new),
+                    );
+                    registry.spawn(&node.id, self.preset_id.as_deref(), index, program, cmd)
+                        .and_then(|launch_id| {
+                            if !planned.wait {
+                                return Ok(());
+                            }
+                            let info = registry.wait(launch_id, None)?;
+                            match info.state {
+                                LaunchState::Exited => Ok(()),
+                                _ => Err(info.error.unwrap_or_else(|| format!("Process exited with code: {:?}", info.exit_code))),
+                            }
+                        })
+                        .map_err(|e| {
+                            varo_log!("[Node Manager] Error launching command: {}", e);
+                            VaroError::execution(format!("Failed to execute command: {}", e))
+                        })
+                }
+            };
+
+            // Handle execution result
+            match result {
+                Ok(()) => {
+                    varo_log!("[Node Manager] Command {}/{} executed successfully", index + 1, node.commands.len());
+                },
+                Err(e) => {
+                    varo_log!("[Node Manager] Command {}/{} failed: {}", index + 1, node.commands.len(), e);
+                    return Err(e);
+                }
+            }
+        }
+
+        varo_log!("[Node Manager] All commands executed successfully");
+        Ok(())
+    }
+
+}
+
+/// Environment prepared for launching a node
+struct LaunchEnv {
+    /// System env with preset, node and override changes applied, used for `${VAR}` expansion
+    expansion_env: HashMap<String, String>,
+    /// Values for built-in tokens
+    context: ExpandContext,
+    /// Variables changed relative to the system env, with where each change came from
+    changes: Vec<PlannedEnvVar>,
+    env_vars: Option<HashMap<String, String>>,
+    unset_vars: Option<Vec<String>>,
+    /// Problems that prevent launching, e.g. failed `${VAR:?message}` tokens
+    errors: Vec<String>,
+    /// Whether relative programs are also looked up in VARO_PATH
+    varo_path_fallback: bool,
+}
+
+/// Check that an expanded working directory exists.
+/// Relative directories are resolved against the folder containing the node file.
+fn resolve_working_dir(cwd: Option<&str>, node: &VaroNode) -> Result<Option<PathBuf>, String> {
+    let cwd = match cwd {
+        Some(cwd) => cwd,
+        None => return Ok(None),
+    };
+
+    let expanded = PathBuf::from(cwd);
+    let resolved = if expanded.is_relative() {
+        match node.filepath.as_deref().and_then(|f| Path::new(f).parent()) {
+            Some(node_dir) => node_dir.join(&expanded),
+            None => expanded,
+        }
+    } else {
+        expanded
+    };
+
+    if !resolved.is_dir() {
+        return Err(format!(
+            "Working directory for node '{}' does not exist: {}",
+            node.name,
+            resolved.display()
+        ));
+    }
+
+    Ok(Some(resolved))
 }
\ No newline at end of file
@@ -1,3 +1,7 @@
+pub mod access;
+pub mod cache;
+pub mod icons;
+pub mod loader;
 pub mod manager;
 
 pub use manager::NodeManager;
\ No newline at end of file
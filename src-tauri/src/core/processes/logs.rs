@@ -0,0 +1,224 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use serde::Serialize;
+use serde_json::Value;
+use crate::utils::config::get_user_varo_dir;
+use crate::utils::log::varo_log;
+
+const DEFAULT_MAX_FILES: usize = 200;
+const DEFAULT_MAX_AGE_DAYS: u64 = 14;
+
+/// Start of every launch log name, so pruning never touches other files in a shared log folder
+const LOG_FILE_PREFIX: &str = "varo-launch-";
+
+/// Largest chunk returned by a single log read
+const MAX_READ_BYTES: usize = 256 * 1024;
+
+/// A slice of a launch log, returned when tailing
+#[derive(Debug, Serialize, Clone)]
+pub struct LaunchLogChunk {
+    pub path: String,
+    pub offset: u64,
+    pub next_offset: u64,
+    pub size: u64,
+    pub content: String,
+}
+
+/// Settings from the `logs` config section controlling where launch output goes and how long it is kept
+#[derive(Debug, Clone)]
+pub struct LaunchLogSettings {
+    pub enabled: bool,
+    pub directory: Option<PathBuf>,
+    pub max_files: usize,
+    pub max_age_days: u64,
+}
+
+impl Default for LaunchLogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: get_user_varo_dir().map(|dir| dir.join("logs")),
+            max_files: DEFAULT_MAX_FILES,
+            max_age_days: DEFAULT_MAX_AGE_DAYS,
+        }
+    }
+}
+
+impl LaunchLogSettings {
+    pub fn from_config(logs_config: &Value) -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: logs_config.get("enabled").and_then(|v| v.as_bool()).unwrap_or(defaults.enabled),
+            directory: logs_config.get("directory")
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from)
+                .or(defaults.directory),
+            max_files: logs_config.get("max_files")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize)
+                .unwrap_or(defaults.max_files),
+            max_age_days: logs_config.get("max_age_days").and_then(|v| v.as_u64()).unwrap_or(defaults.max_age_days),
+        }
+    }
+
+    /// Create the log file for a new launch, pruning old logs first.
+    /// Returns `None` when logging is disabled or the file cannot be created.
+    pub fn create_log_file(&self, launch_id: u64, node_id: &str) -> Option<(PathBuf, File)> {
+        if !self.enabled {
+            return None;
+        }
+        let directory = self.directory.as_ref()?;
+
+        if let Err(e) = fs::create_dir_all(directory) {
+            eprintln!("Warning: Failed to create log directory '{}': {}", directory.display(), e);
+            return None;
+        }
+
+        // Make room for the new file within the retention limits
+        self.prune(self.max_files.saturating_sub(1));
+
+        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let safe_node_id: String = node_id.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let path = directory.join(format!("{}{}_{}_{}.log", LOG_FILE_PREFIX, timestamp, safe_node_id, launch_id));
+
+        match File::create(&path) {
+            Ok(file) => Some((path, file)),
+            Err(e) => {
+                eprintln!("Warning: Failed to create launch log '{}': {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Delete launch logs older than `max_age_days`, then the oldest logs beyond `keep` files.
+    /// Only files named like the logs Varo writes are considered.
+    pub fn prune(&self, keep: usize) {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        let max_age = Duration::from_secs(self.max_age_days * 24 * 60 * 60);
+        let now = SystemTime::now();
+
+        let mut logs: Vec<(SystemTime, PathBuf)> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| is_launch_log(path))
+            .filter_map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((modified, path))
+            })
+            .collect();
+
+        // Newest first, so everything past `keep` is the oldest
+        logs.sort_by(|a, b| b.0.cmp(&a.0));
+
+        for (index, (modified, path)) in logs.iter().enumerate() {
+            let expired = now.duration_since(*modified).map(|age| age > max_age).unwrap_or(false);
+            if expired || index >= keep {
+                varo_log!("[Launch Logs] Removing old log: {}", path.display());
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// True for files named `varo-launch-<timestamp>_<node id>_<launch id>.log`
+fn is_launch_log(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+    let stem = match name.strip_prefix(LOG_FILE_PREFIX).and_then(|rest| rest.strip_suffix(".log")) {
+        Some(stem) => stem,
+        None => return false,
+    };
+    // The node id may contain underscores, so take the timestamp and launch id from the ends
+    let (timestamp, rest) = match stem.split_once('_') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let launch_id = match rest.rsplit_once('_') {
+        Some((_, launch_id)) => launch_id,
+        None => return false,
+    };
+    timestamp.len() == 15
+        && timestamp.chars().enumerate().all(|(i, c)| if i == 8 { c == '-' } else { c.is_ascii_digit() })
+        && !launch_id.is_empty()
+        && launch_id.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Read a launch log from `offset`, returning at most `max_bytes` (capped at 256 KiB).
+/// A character cut off at the end of the chunk is left for the next read, so `next_offset` always
+/// falls on a character boundary. Pass it back in to follow the log as it grows.
+pub fn read_log_chunk(path: &Path, offset: u64, max_bytes: Option<usize>) -> Result<LaunchLogChunk, String> {
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to open log '{}': {}", path.display(), e))?;
+    let size = file.metadata()
+        .map_err(|e| format!("Failed to read log metadata '{}': {}", path.display(), e))?
+        .len();
+
+    let offset = offset.min(size);
+    let max_bytes = max_bytes.unwrap_or(MAX_READ_BYTES).min(MAX_READ_BYTES);
+
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("Failed to seek log '{}': {}", path.display(), e))?;
+    let mut buffer = Vec::new();
+    // Up to 3 more bytes are read so a character longer than `max_bytes` can still be returned whole
+    file.take(max_bytes as u64 + 3).read_to_end(&mut buffer)
+        .map_err(|e| format!("Failed to read log '{}': {}", path.display(), e))?;
+    buffer.truncate(utf8_chunk_len(&buffer, max_bytes));
+
+    Ok(LaunchLogChunk {
+        path: path.to_string_lossy().to_string(),
+        offset,
+        next_offset: offset + buffer.len() as u64,
+        size,
+        content: String::from_utf8_lossy(&buffer).to_string(),
+    })
+}
+
+/// How much of `bytes` to return: at most `max_bytes`, without a UTF-8 character cut off at the end.
+/// When the first character alone is longer than `max_bytes` it is returned whole, so reads keep
+/// moving forward. Invalid bytes are kept; they are shown as replacement characters.
+fn utf8_chunk_len(bytes: &[u8], max_bytes: usize) -> usize {
+    let limit = bytes.len().min(max_bytes);
+    let chunk = &bytes[..limit];
+    // A character is at most 4 bytes, so its first byte is among the last 3 when it is cut off
+    for back in 1..=limit.min(3) {
+        let byte = chunk[limit - back];
+        if byte & 0xC0 == 0x80 {
+            continue; // Continuation byte
+        }
+        if utf8_width(byte) <= back {
+            return limit;
+        }
+        if back < limit {
+            return limit - back;
+        }
+        // Only the first character is in the chunk, and it is cut off
+        let width = utf8_width(bytes[0]);
+        return if width <= bytes.len() { width } else { 0 };
+    }
+    limit
+}
+
+/// Length of the UTF-8 character starting with `byte`; 1 for bytes that cannot start one
+fn utf8_width(byte: u8) -> usize {
+    match byte {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    }
+}
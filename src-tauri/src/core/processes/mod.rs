@@ -0,0 +1,5 @@
+pub mod logs;
+pub mod registry;
+
+pub use logs::{LaunchLogChunk, LaunchLogSettings};
+pub use registry::{LaunchEvent, ProcessRegistry};
\ No newline at end of file
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use crate::models::entities::{LaunchInfo, LaunchState};
+use crate::core::processes::logs::{read_log_chunk, LaunchLogChunk, LaunchLogSettings};
+use crate::utils::log::varo_log;
+
+/// How often running children are polled for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Finished launches kept for the UI before the oldest are dropped
+const MAX_FINISHED_LAUNCHES: usize = 100;
+
+/// Emitted when a launch starts or finishes
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "launch", rename_all = "lowercase")]
+pub enum LaunchEvent {
+    Started(LaunchInfo),
+    Exited(LaunchInfo),
+}
+
+impl LaunchEvent {
+    /// Event name used when forwarding to the frontend
+    pub fn name(&self) -> &'static str {
+        match self {
+            LaunchEvent::Started(_) => "launch-started",
+            LaunchEvent::Exited(_) => "launch-exited",
+        }
+    }
+
+    pub fn info(&self) -> &LaunchInfo {
+        match self {
+            LaunchEvent::Started(info) | LaunchEvent::Exited(info) => info,
+        }
+    }
+}
+
+pub type LaunchListener = Arc<dyn Fn(&LaunchEvent) + Send + Sync>;
+
+struct LaunchEntry {
+    info: LaunchInfo,
+    child: Option<Child>,
+    kill_requested: bool,
+}
+
+struct RegistryInner {
+    launches: Mutex<HashMap<u64, LaunchEntry>>,
+    changed: Condvar,
+    next_id: AtomicU64,
+    listener: Mutex<Option<LaunchListener>>,
+    log_settings: Mutex<LaunchLogSettings>,
+}
+
+/// Tracks programs started for node commands: PID, state and exit code.
+/// Cloning shares the same registry.
+#[derive(Clone)]
+pub struct ProcessRegistry {
+    inner: Arc<RegistryInner>,
+}
+
+impl std::fmt::Debug for ProcessRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ProcessRegistry")
+            .field("launches", &self.list(true).len())
+            .finish()
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl ProcessRegistry {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(RegistryInner {
+                launches: Mutex::new(HashMap::new()),
+                changed: Condvar::new(),
+                next_id: AtomicU64::new(1),
+                listener: Mutex::new(None),
+                log_settings: Mutex::new(LaunchLogSettings::default()),
+            }),
+        }
+    }
+
+    /// Register a callback invoked for every start and exit event
+    pub fn set_listener<F: Fn(&LaunchEvent) + Send + Sync + 'static>(&self, listener: F) {
+        *self.inner.listener.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(listener));
+    }
+
+    /// Replace the launch log settings and apply their retention limits
+    pub fn set_log_settings(&self, settings: LaunchLogSettings) {
+        settings.prune(settings.max_files);
+        *self.inner.log_settings.lock().unwrap_or_else(|e| e.into_inner()) = settings;
+    }
+
+    fn lock_launches(&self) -> MutexGuard<'_, HashMap<u64, LaunchEntry>> {
+        self.inner.launches.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn emit(&self, event: LaunchEvent) {
+        let listener = self.inner.listener.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(listener) = listener {
+            listener(&event);
+        }
+    }
+
+    /// Spawn a command and start tracking it. Returns the launch id.
+    /// Spawn failures are recorded as a failed launch and returned as an error.
+    pub fn spawn(
+        &self,
+        node_id: &str,
+        preset_id: Option<&str>,
+        command_index: usize,
+        path: &str,
+        mut command: Command,
+    ) -> Result<u64, String> {
+        let launch_id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut info = LaunchInfo {
+            launch_id,
+            node_id: node_id.to_string(),
+            preset_id: preset_id.map(|s| s.to_string()),
+            command_index,
+            path: path.to_string(),
+            pid: None,
+            started_at: now_millis(),
+            finished_at: None,
+            state: LaunchState::Running,
+            exit_code: None,
+            error: None,
+            log_path: None,
+        };
+
+        // Send stdout and stderr to a per-launch log file. The child writes to the file
+        // directly, so it keeps running normally if Varo exits first.
+        let log_file = self.inner.log_settings.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .create_log_file(launch_id, node_id);
+        if let Some((log_path, file)) = log_file {
+            match file.try_clone() {
+                Ok(stderr_file) => {
+                    command.stdout(Stdio::from(file));
+                    command.stderr(Stdio::from(stderr_file));
+                    info.log_path = Some(log_path.to_string_lossy().to_string());
+                }
+                Err(e) => eprintln!("Warning: Failed to open launch log '{}': {}", log_path.display(), e),
+            }
+        }
+
+        let child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let message = format!("Launch failed: {}", e);
+                info.state = LaunchState::Failed;
+                info.finished_at = Some(info.started_at);
+                info.error = Some(message.clone());
+                self.insert(LaunchEntry { info: info.clone(), child: None, kill_requested: false });
+                self.emit(LaunchEvent::Exited(info));
+                return Err(message);
+            }
+        };
+
+        info.pid = Some(child.id());
+        varo_log!("[Process Registry] Launch {} started: {} (PID {})", launch_id, path, child.id());
+        self.insert(LaunchEntry { info: info.clone(), child: Some(child), kill_requested: false });
+        self.emit(LaunchEvent::Started(info));
+
+        let registry = self.clone();
+        std::thread::spawn(move || registry.monitor(launch_id));
+
+        Ok(launch_id)
+    }
+
+    fn insert(&self, entry: LaunchEntry) {
+        let mut launches = self.lock_launches();
+        launches.insert(entry.info.launch_id, entry);
+
+        // Drop the oldest finished launches once the history grows too long
+        let mut finished: Vec<u64> = launches.values()
+            .filter(|e| e.info.state != LaunchState::Running)
+            .map(|e| e.info.launch_id)
+            .collect();
+        if finished.len() > MAX_FINISHED_LAUNCHES {
+            finished.sort();
+            for id in &finished[..finished.len() - MAX_FINISHED_LAUNCHES] {
+                launches.remove(id);
+            }
+        }
+    }
+
+    /// Poll a child until it exits, then record its exit status
+    fn monitor(&self, launch_id: u64) {
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+
+            let finished = {
+                let mut launches = self.lock_launches();
+                let entry = match launches.get_mut(&launch_id) {
+                    Some(entry) => entry,
+                    None => return,
+                };
+                let child = match entry.child.as_mut() {
+                    Some(child) => child,
+                    None => return,
+                };
+
+                match child.try_wait() {
+                    Ok(Some(status)) => {
+                        Self::finish(entry, Some(status), None);
+                        Some(entry.info.clone())
+                    }
+                    Ok(None) => None,
+                    Err(e) => {
+                        Self::finish(entry, None, Some(format!("Failed to query process status: {}", e)));
+                        Some(entry.info.clone())
+                    }
+                }
+            };
+
+            if let Some(info) = finished {
+                varo_log!("[Process Registry] Launch {} finished: {:?} (exit code {:?})", launch_id, info.state, info.exit_code);
+                self.inner.changed.notify_all();
+                self.emit(LaunchEvent::Exited(info));
+                return;
+            }
+        }
+    }
+
+    fn finish(entry: &mut LaunchEntry, status: Option<ExitStatus>, error: Option<String>) {
+        entry.child = None;
+        entry.info.finished_at = Some(now_millis());
+        entry.info.exit_code = status.and_then(|s| s.code());
+        entry.info.error = error;
+        entry.info.state = match status {
+            _ if entry.kill_requested => LaunchState::Killed,
+            Some(status) if status.success() => LaunchState::Exited,
+            _ => LaunchState::Failed,
+        };
+    }
+
+    /// List tracked launches, oldest first. Finished launches are included on request.
+    pub fn list(&self, include_finished: bool) -> Vec<LaunchInfo> {
+        let mut launches: Vec<LaunchInfo> = self.lock_launches()
+            .values()
+            .filter(|e| include_finished || e.info.state == LaunchState::Running)
+            .map(|e| e.info.clone())
+            .collect();
+        launches.sort_by_key(|info| info.launch_id);
+        launches
+    }
+
+    pub fn get(&self, launch_id: u64) -> Option<LaunchInfo> {
+        self.lock_launches().get(&launch_id).map(|e| e.info.clone())
+    }
+
+    /// Read captured output of a launch starting at `offset`
+    pub fn read_log(&self, launch_id: u64, offset: u64, max_bytes: Option<usize>) -> Result<LaunchLogChunk, String> {
+        let info = self.get(launch_id).ok_or_else(|| format!("Launch not found: {}", launch_id))?;
+        let log_path = info.log_path.ok_or_else(|| format!("Launch {} has no log file", launch_id))?;
+        read_log_chunk(Path::new(&log_path), offset, max_bytes)
+    }
+
+    /// Kill a running launch. The exit is recorded (as `killed`) by the monitor thread.
+    pub fn kill(&self, launch_id: u64) -> Result<(), String> {
+        let mut launches = self.lock_launches();
+        let entry = launches.get_mut(&launch_id)
+            .ok_or_else(|| format!("Launch not found: {}", launch_id))?;
+        let child = entry.child.as_mut()
+            .ok_or_else(|| format!("Launch {} is not running", launch_id))?;
+
+        child.kill().map_err(|e| format!("Failed to kill launch {}: {}", launch_id, e))?;
+        entry.kill_requested = true;
+        varo_log!("[Process Registry] Kill requested for launch {}", launch_id);
+        Ok(())
+    }
+
+    /// Block until a launch finishes or the timeout elapses, returning its latest info
+    pub fn wait(&self, launch_id: u64, timeout: Option<Duration>) -> Result<LaunchInfo, String> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut launches = self.lock_launches();
+
+        loop {
+            let info = launches.get(&launch_id)
+                .map(|e| e.info.clone())
+                .ok_or_else(|| format!("Launch not found: {}", launch_id))?;
+            if info.state != LaunchState::Running {
+                return Ok(info);
+            }
+
+            launches = match deadline {
+                None => self.inner.changed.wait(launches).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(info);
+                    }
+                    self.inner.changed.wait_timeout(launches, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+            };
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::utils::platform;
 pub struct SystemInfo {
     pub username: String,
     pub platform: String,
+    pub groups: Vec<String>,
 }
 
 impl SystemInfo {
@@ -11,6 +12,7 @@ impl SystemInfo {
         Self {
             username: platform::get_os_username(),
             platform: platform::get_platform(),
+            groups: platform::get_os_user_groups(),
         }
     }
 
@@ -21,4 +23,8 @@ impl SystemInfo {
     pub fn get_platform(&self) -> &str {
         &self.platform
     }
+
+    pub fn get_groups(&self) -> &[String] {
+        &self.groups
+    }
 }
\ No newline at end of file
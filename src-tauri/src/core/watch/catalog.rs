@@ -0,0 +1,51 @@
+use serde::Serialize;
+use crate::models::diagnostics::Diagnostic;
+use crate::models::entities::{CatalogDelta, EntryDelta, EnvPreset, VaroNode};
+
+/// What the frontend sees of the catalog, captured before and after files are reloaded
+#[derive(Debug, Clone)]
+pub struct CatalogSnapshot {
+    pub nodes: Vec<VaroNode>,
+    pub presets: Vec<EnvPreset>,
+    pub active_preset_ids: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CatalogSnapshot {
+    /// Changes from `self` to `after`. Entries are compared by their serialized form, which is
+    /// exactly what the frontend receives.
+    pub fn delta(&self, after: &CatalogSnapshot, config_changed: bool) -> CatalogDelta {
+        CatalogDelta {
+            nodes: diff_entries(&self.nodes, &after.nodes, |node| &node.id),
+            presets: diff_entries(&self.presets, &after.presets, |preset| &preset.id),
+            active_preset_ids: (self.active_preset_ids != after.active_preset_ids)
+                .then(|| after.active_preset_ids.clone()),
+            diagnostics_changed: to_json(&self.diagnostics) != to_json(&after.diagnostics),
+            config_changed,
+        }
+    }
+}
+
+fn diff_entries<T, F>(before: &[T], after: &[T], id: F) -> EntryDelta<T>
+where
+    T: Serialize + Clone,
+    F: Fn(&T) -> &String,
+{
+    let mut delta = EntryDelta::default();
+    for entry in after {
+        match before.iter().find(|old| id(old) == id(entry)) {
+            None => delta.added.push(entry.clone()),
+            Some(old) if to_json(old) != to_json(entry) => delta.changed.push(entry.clone()),
+            Some(_) => {}
+        }
+    }
+    delta.removed = before.iter()
+        .filter(|old| !after.iter().any(|entry| id(entry) == id(old)))
+        .map(|old| id(old).clone())
+        .collect();
+    delta
+}
+
+fn to_json<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
\ No newline at end of file
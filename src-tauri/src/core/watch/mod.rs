@@ -0,0 +1,5 @@
+pub mod catalog;
+pub mod watcher;
+
+pub use catalog::CatalogSnapshot;
+pub use watcher::{FileChanges, FileWatcher, WatchSettings, WatchTarget};
\ No newline at end of file
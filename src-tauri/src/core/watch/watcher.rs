@@ -0,0 +1,255 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::Value;
+use crate::utils::log::varo_log;
+use crate::utils::platform::is_network_path;
+
+const DEFAULT_DEBOUNCE_MS: u64 = 300;
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+
+/// A burst of events is flushed after at most this many debounce periods, so a folder that keeps
+/// changing (e.g. a long copy) still gets reloaded while it happens
+const MAX_DEBOUNCE_PERIODS: u32 = 10;
+
+/// How changes are detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchMode {
+    /// Native notifications, polling network shares and folders native watching fails on
+    Auto,
+    /// Native notifications only
+    Native,
+    /// Poll every folder, for file systems that do not report changes
+    Poll,
+}
+
+/// Settings from the `watch` config section
+#[derive(Debug, Clone)]
+pub struct WatchSettings {
+    pub enabled: bool,
+    pub mode: WatchMode,
+    pub debounce: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: WatchMode::Auto,
+            debounce: Duration::from_millis(DEFAULT_DEBOUNCE_MS),
+            poll_interval: Duration::from_secs(DEFAULT_POLL_INTERVAL_SECS),
+        }
+    }
+}
+
+impl WatchSettings {
+    pub fn from_config(watch_config: &Value) -> Self {
+        let defaults = Self::default();
+        let mode = match watch_config.get("mode").and_then(|v| v.as_str()) {
+            None | Some("auto") => WatchMode::Auto,
+            Some("native") => WatchMode::Native,
+            Some("poll") => WatchMode::Poll,
+            Some(other) => {
+                eprintln!("Warning: Unknown watch mode '{}', using 'auto'", other);
+                WatchMode::Auto
+            }
+        };
+        Self {
+            enabled: watch_config.get("enabled").and_then(|v| v.as_bool()).unwrap_or(defaults.enabled),
+            mode,
+            debounce: watch_config.get("debounce_ms")
+                .and_then(|v| v.as_u64())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.debounce),
+            poll_interval: watch_config.get("poll_interval_secs")
+                .and_then(|v| v.as_u64())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.poll_interval),
+        }
+    }
+}
+
+/// A folder to watch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchTarget {
+    pub path: PathBuf,
+    pub recursive: bool,
+}
+
+/// Paths changed during one debounce window
+#[derive(Debug, Clone, Default)]
+pub struct FileChanges {
+    pub paths: BTreeSet<PathBuf>,
+    /// Events were lost (e.g. the OS queue overflowed), so everything should be reloaded
+    pub rescan: bool,
+}
+
+enum WatchMessage {
+    Paths(Vec<PathBuf>),
+    Rescan,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Native,
+    Poll,
+}
+
+/// Watches folders and reports their changes, debounced, to a callback on a background thread.
+/// Dropping the watcher stops it.
+pub struct FileWatcher {
+    settings: WatchSettings,
+    sender: Sender<WatchMessage>,
+    native: Option<RecommendedWatcher>,
+    poll: Option<PollWatcher>,
+    watched: Vec<(WatchTarget, Backend)>,
+}
+
+impl std::fmt::Debug for FileWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileWatcher")
+            .field("settings", &self.settings)
+            .field("watched", &self.watched)
+            .finish()
+    }
+}
+
+impl FileWatcher {
+    /// Start the debounce thread. `on_change` receives every batch of changes; nothing is watched
+    /// until [`FileWatcher::set_targets`] is called.
+    pub fn start<F>(settings: WatchSettings, mut on_change: F) -> Self
+    where
+        F: FnMut(FileChanges) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let debounce = settings.debounce;
+        std::thread::spawn(move || {
+            while let Some(changes) = Self::next_changes(&receiver, debounce) {
+                varo_log!("[File Watcher] {} paths changed{}", changes.paths.len(), if changes.rescan { " (rescan)" } else { "" });
+                on_change(changes);
+            }
+        });
+
+        Self {
+            settings,
+            sender,
+            native: None,
+            poll: None,
+            watched: Vec::new(),
+        }
+    }
+
+    /// Wait for the next change and collect everything that follows within the debounce time.
+    /// Returns `None` once the watcher has been dropped.
+    fn next_changes(receiver: &Receiver<WatchMessage>, debounce: Duration) -> Option<FileChanges> {
+        let mut changes = FileChanges::default();
+        let add = |changes: &mut FileChanges, message: WatchMessage| match message {
+            WatchMessage::Paths(paths) => changes.paths.extend(paths),
+            WatchMessage::Rescan => changes.rescan = true,
+        };
+
+        add(&mut changes, receiver.recv().ok()?);
+        for _ in 0..MAX_DEBOUNCE_PERIODS {
+            match receiver.recv_timeout(debounce) {
+                Ok(message) => add(&mut changes, message),
+                // Quiet for a whole period, or the watcher was dropped
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return Some(changes),
+            }
+            // Drain what arrived meanwhile without waiting
+            while let Ok(message) = receiver.try_recv() {
+                add(&mut changes, message);
+            }
+        }
+        Some(changes)
+    }
+
+    /// Watch exactly `targets`. Folders that are no longer listed are released, new ones are
+    /// watched; folders that do not exist yet are skipped until the targets are set again.
+    pub fn set_targets(&mut self, targets: &[WatchTarget]) {
+        let (kept, stale): (Vec<_>, Vec<_>) = std::mem::take(&mut self.watched)
+            .into_iter()
+            .partition(|(target, _)| targets.contains(target) && target.path.is_dir());
+        for (target, backend) in stale {
+            let result = match backend {
+                Backend::Native => self.native.as_mut().map(|w| w.unwatch(&target.path)),
+                Backend::Poll => self.poll.as_mut().map(|w| w.unwatch(&target.path)),
+            };
+            if let Some(Err(e)) = result {
+                varo_log!("[File Watcher] Failed to unwatch {}: {}", target.path.display(), e);
+            }
+        }
+        self.watched = kept;
+
+        for target in targets {
+            if !target.path.is_dir() || self.watched.iter().any(|(watched, _)| watched == target) {
+                continue;
+            }
+            match self.watch(target) {
+                Ok(backend) => {
+                    varo_log!("[File Watcher] Watching {} ({:?})", target.path.display(), backend);
+                    self.watched.push((target.clone(), backend));
+                }
+                Err(e) => eprintln!("Warning: Failed to watch '{}': {}", target.path.display(), e),
+            }
+        }
+    }
+
+    fn watch(&mut self, target: &WatchTarget) -> notify::Result<Backend> {
+        let mode = if target.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        let use_poll = match self.settings.mode {
+            WatchMode::Poll => true,
+            WatchMode::Native => false,
+            // Native notifications are not delivered for changes made on other machines
+            WatchMode::Auto => is_network_path(&target.path),
+        };
+
+        if !use_poll {
+            let result = self.native_watcher().and_then(|w| w.watch(&target.path, mode));
+            match result {
+                Ok(()) => return Ok(Backend::Native),
+                Err(e) if self.settings.mode == WatchMode::Native => return Err(e),
+                Err(e) => varo_log!("[File Watcher] Native watching failed for {}, polling instead: {}", target.path.display(), e),
+            }
+        }
+
+        self.poll_watcher()?.watch(&target.path, mode)?;
+        Ok(Backend::Poll)
+    }
+
+    fn native_watcher(&mut self) -> notify::Result<&mut RecommendedWatcher> {
+        if self.native.is_none() {
+            self.native = Some(RecommendedWatcher::new(Self::event_handler(&self.sender), Config::default())?);
+        }
+        Ok(self.native.as_mut().expect("native watcher was just created"))
+    }
+
+    fn poll_watcher(&mut self) -> notify::Result<&mut PollWatcher> {
+        if self.poll.is_none() {
+            let config = Config::default().with_poll_interval(self.settings.poll_interval);
+            self.poll = Some(PollWatcher::new(Self::event_handler(&self.sender), config)?);
+        }
+        Ok(self.poll.as_mut().expect("poll watcher was just created"))
+    }
+
+    /// Forward the events that can change the catalog to the debounce thread
+    fn event_handler(sender: &Sender<WatchMessage>) -> impl FnMut(notify::Result<Event>) + Send + 'static {
+        let sender = sender.clone();
+        move |result: notify::Result<Event>| {
+            let message = match result {
+                Ok(event) if event.need_rescan() => WatchMessage::Rescan,
+                // Reading files does not change them
+                Ok(event) if matches!(event.kind, EventKind::Access(_)) => return,
+                Ok(event) => WatchMessage::Paths(event.paths),
+                Err(e) => {
+                    varo_log!("[File Watcher] Error: {}", e);
+                    return;
+                }
+            };
+            let _ = sender.send(message);
+        }
+    }
+}
@@ -1,12 +1,14 @@
-use std::sync::Mutex;
-use tauri::{Builder, Manager};
+use std::time::Duration;
+use tauri::{Builder, Emitter, Manager};
+use tauri::http::{header, Request, Response, StatusCode};
 
 mod models;
 mod utils;
 mod core;
+pub mod cli;
 
-use crate::core::VaroCore;
-use crate::utils::commands::execute_program;
+use crate::core::{CoreOptions, VaroCore};
+use crate::utils::export::{ExportFormat, EXPORT_FORMATS};
 use crate::models::errors::VaroError;
 
 // Helper function to convert VaroError to String for Tauri
@@ -17,89 +19,302 @@ fn handle_error<T>(result: Result<T, VaroError>) -> Result<T, String> {
     })
 }
 
+/// Serve `varo-icon://localhost/<key>?size=<px>` from the icons of the loaded nodes. Keys are
+/// content hashes, so a response never changes and the webview may cache it for good.
+fn serve_icon(core: &VaroCore, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let key = request.uri().path().trim_start_matches('/');
+    let size = request.uri().query()
+        .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("size=")))
+        .and_then(|size| size.parse::<u32>().ok())
+        .filter(|size| *size > 0);
+
+    let icon = match core.sync_get_icon(key, size) {
+        Some(icon) => icon,
+        None => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Vec::new())
+                .unwrap_or_default();
+        }
+    };
+
+    let etag = match size {
+        Some(size) => format!("\"{}-{}\"", key, size),
+        None => format!("\"{}\"", key),
+    };
+    let not_modified = request.headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag));
+
+    let response = Response::builder()
+        .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+        .header(header::ETAG, &etag);
+    let response = if not_modified {
+        response.status(StatusCode::NOT_MODIFIED).body(Vec::new())
+    } else {
+        response.header(header::CONTENT_TYPE, &icon.mime).body(icon.bytes.clone())
+    };
+    response.unwrap_or_default()
+}
+
 #[tauri::command]
-fn get_config(state: tauri::State<Mutex<VaroCore>>) -> Result<serde_json::Value, String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn get_config(state: tauri::State<VaroCore>) -> Result<serde_json::Value, String> {
     Ok(state.sync_get_config())
 }
 
 #[tauri::command]
-fn get_env_presets(state: tauri::State<Mutex<VaroCore>>) -> Result<Vec<crate::models::entities::EnvPreset>, String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn get_env_presets(state: tauri::State<VaroCore>) -> Result<Vec<crate::models::entities::EnvPreset>, String> {
     Ok(state.sync_get_all_presets())
 }
 
 #[tauri::command]
-fn get_selected_env_preset(state: tauri::State<Mutex<VaroCore>>) -> Result<Option<crate::models::entities::EnvPreset>, String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn get_selected_env_preset(state: tauri::State<VaroCore>) -> Result<Option<crate::models::entities::EnvPreset>, String> {
     Ok(state.sync_get_selected_preset())
 }
 
 #[tauri::command]
-fn select_env_preset(id: String, state: tauri::State<Mutex<VaroCore>>) -> Result<(), String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn select_env_preset(id: String, state: tauri::State<VaroCore>) -> Result<(), String> {
     handle_error(state.sync_select_preset(&id))
 }
 
 #[tauri::command]
-fn get_os_username(state: tauri::State<Mutex<VaroCore>>) -> Result<String, String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn get_active_env_presets(state: tauri::State<VaroCore>) -> Result<Vec<crate::models::entities::EnvPreset>, String> {
+    Ok(state.sync_get_active_presets())
+}
+
+#[tauri::command]
+fn push_env_preset(id: String, state: tauri::State<VaroCore>) -> Result<(), String> {
+    handle_error(state.sync_push_preset(&id))
+}
+
+#[tauri::command]
+fn pop_env_preset(state: tauri::State<VaroCore>) -> Result<Option<crate::models::entities::EnvPreset>, String> {
+    handle_error(state.sync_pop_preset())
+}
+
+#[tauri::command]
+fn reorder_env_presets(ids: Vec<String>, state: tauri::State<VaroCore>) -> Result<(), String> {
+    handle_error(state.sync_reorder_presets(&ids))
+}
+
+#[tauri::command]
+fn create_env_preset(preset: serde_json::Value, directory: Option<String>, state: tauri::State<VaroCore>) -> Result<crate::models::entities::EnvPreset, String> {
+    handle_error(state.sync_create_preset(directory.as_deref(), &preset))
+}
+
+#[tauri::command]
+fn update_env_preset(id: String, fields: serde_json::Value, state: tauri::State<VaroCore>) -> Result<crate::models::entities::EnvPreset, String> {
+    handle_error(state.sync_update_preset(&id, &fields))
+}
+
+#[tauri::command]
+fn duplicate_env_preset(id: String, new_id: String, name: Option<String>, directory: Option<String>, state: tauri::State<VaroCore>) -> Result<crate::models::entities::EnvPreset, String> {
+    handle_error(state.sync_duplicate_preset(&id, &new_id, name.as_deref(), directory.as_deref()))
+}
+
+#[tauri::command]
+fn delete_env_preset(id: String, state: tauri::State<VaroCore>) -> Result<(), String> {
+    handle_error(state.sync_delete_preset(&id))
+}
+
+#[tauri::command]
+fn get_os_username(state: tauri::State<VaroCore>) -> Result<String, String> {
     Ok(state.get_username().to_string())
 }
 
 #[tauri::command]
-fn get_platform(state: tauri::State<Mutex<VaroCore>>) -> Result<String, String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn get_platform(state: tauri::State<VaroCore>) -> Result<String, String> {
     Ok(state.get_platform().to_string())
 }
 
 #[tauri::command]
-fn reload_config(state: tauri::State<Mutex<VaroCore>>) -> Result<(), String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn reload_config(state: tauri::State<VaroCore>) -> Result<(), String> {
     handle_error(state.sync_reload_config())
 }
 
 #[tauri::command]
-fn get_nodes(state: tauri::State<Mutex<VaroCore>>) -> Result<Vec<crate::models::entities::VaroNode>, String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn get_nodes(state: tauri::State<VaroCore>) -> Result<Vec<crate::models::entities::VaroNode>, String> {
     Ok(state.sync_get_all_nodes())
 }
 
 #[tauri::command]
-fn execute_node(id: String, state: tauri::State<Mutex<VaroCore>>) -> Result<(), String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn execute_node(id: String, state: tauri::State<VaroCore>) -> Result<(), String> {
     handle_error(state.sync_execute_node(&id))
 }
 
 #[tauri::command]
-fn show_node_in_folder(id: String, state: tauri::State<Mutex<VaroCore>>) -> Result<(), String> {
-    let state = state.lock().map_err(|e| format!("Failed to acquire state lock: {}", e))?;
+fn get_diagnostics(state: tauri::State<VaroCore>) -> Result<Vec<crate::models::diagnostics::Diagnostic>, String> {
+    Ok(state.sync_get_diagnostics())
+}
+
+#[tauri::command]
+fn get_scan_stats(state: tauri::State<VaroCore>) -> Result<crate::models::entities::ScanStats, String> {
+    Ok(state.sync_get_scan_stats())
+}
+
+#[tauri::command]
+fn get_catalog_status(state: tauri::State<VaroCore>) -> Result<crate::models::entities::CatalogStatus, String> {
+    Ok(state.sync_get_catalog_status())
+}
+
+#[tauri::command]
+fn get_hidden_nodes(state: tauri::State<VaroCore>) -> Result<Vec<crate::models::entities::NodeAccessReport>, String> {
+    Ok(state.sync_get_hidden_nodes())
+}
+
+#[tauri::command]
+fn explain_node_access(id: String, state: tauri::State<VaroCore>) -> Result<crate::models::entities::NodeAccessReport, String> {
+    handle_error(state.sync_explain_node_access(&id))
+}
+
+#[tauri::command]
+fn plan_node_launch(id: String, state: tauri::State<VaroCore>) -> Result<crate::models::entities::LaunchPlan, String> {
+    handle_error(state.sync_plan_node_launch(&id))
+}
+
+#[tauri::command]
+fn list_running(include_finished: Option<bool>, state: tauri::State<VaroCore>) -> Result<Vec<crate::models::entities::LaunchInfo>, String> {
+    Ok(state.sync_list_launches(include_finished.unwrap_or(false)))
+}
+
+#[tauri::command]
+fn kill_launch(launch_id: u64, state: tauri::State<VaroCore>) -> Result<(), String> {
+    handle_error(state.sync_kill_launch(launch_id))
+}
+
+#[tauri::command]
+fn read_launch_log(launch_id: u64, offset: Option<u64>, max_bytes: Option<usize>, state: tauri::State<VaroCore>) -> Result<crate::core::processes::LaunchLogChunk, String> {
+    handle_error(state.sync_read_launch_log(launch_id, offset.unwrap_or(0), max_bytes))
+}
+
+#[tauri::command]
+async fn wait_launch(launch_id: u64, timeout_ms: Option<u64>, state: tauri::State<'_, VaroCore>) -> Result<crate::models::entities::LaunchInfo, String> {
+    // Wait on a blocking thread so other commands keep working
+    let registry = state.process_registry.clone();
+    tauri::async_runtime::spawn_blocking(move || registry.wait(launch_id, timeout_ms.map(Duration::from_millis)))
+        .await
+        .map_err(|e| format!("Failed to wait for launch: {}", e))?
+}
+
+#[tauri::command]
+fn export_env(format: String, node_id: Option<String>, state: tauri::State<VaroCore>) -> Result<String, String> {
+    let format = ExportFormat::parse(&format)
+        .ok_or_else(|| format!("Unknown export format '{}' (expected one of: {})", format, EXPORT_FORMATS.join(", ")))?;
+    handle_error(state.sync_export_env(node_id.as_deref(), format))
+}
+
+#[tauri::command]
+fn show_node_in_folder(id: String, state: tauri::State<VaroCore>) -> Result<(), String> {
     handle_error(state.sync_show_node_in_folder(&id))
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Accept the same startup options as the CLI, e.g. `varo --config /path/config.json`
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args.options,
+        Err(e) => {
+            eprintln!("Warning: Ignoring command-line arguments: {}", e);
+            CoreOptions::default()
+        }
+    };
+
     tauri::Builder::default()
-        .setup(|app| {
-            // Initialize the new VaroCore instead of old AppState
-            let core = VaroCore::new();
+        .setup(move |app| {
+            // Initialize the new VaroCore instead of old AppState, starting from the saved catalog
+            let core = VaroCore::with_options(CoreOptions { catalog_cache: true, ..options.clone() });
+
+            // Forward launch start/exit events to the frontend
+            let app_handle = app.handle().clone();
+            core.set_launch_listener(move |event| {
+                if let Err(e) = app_handle.emit(event.name(), event.info()) {
+                    eprintln!("Varo Error: Failed to emit {}: {}", event.name(), e);
+                }
+            });
             
-            app.manage(Mutex::new(core));
+            // The core locks its managers itself, so commands reading the catalog are not held up
+            // by one that reloads it
+            app.manage(core);
+
+            // Apply node, preset and config files edited while the app runs, and tell the frontend what changed
+            let app_handle = app.handle().clone();
+            let core = app.state::<VaroCore>();
+            core.start_watching(move |changes| {
+                let core = app_handle.state::<VaroCore>();
+                match core.sync_apply_file_changes(&changes) {
+                    Ok(delta) if delta.is_empty() => {}
+                    Ok(delta) => {
+                        if let Err(e) = app_handle.emit("catalog-changed", &delta) {
+                            eprintln!("Varo Error: Failed to emit catalog-changed: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Varo Error: Failed to apply file changes: {}", e),
+                }
+            });
+
+            // When the catalog came from the cache, scan the real one in the background and swap it in
+            let app_handle = app.handle().clone();
+            core.start_catalog_refresh(move |loaded| {
+                let core = app_handle.state::<VaroCore>();
+                match core.sync_install_catalog(loaded).map(|delta| (delta, core.sync_get_catalog_status())) {
+                    Ok((delta, status)) => {
+                        if !delta.is_empty() {
+                            if let Err(e) = app_handle.emit("catalog-changed", &delta) {
+                                eprintln!("Varo Error: Failed to emit catalog-changed: {}", e);
+                            }
+                        }
+                        if let Err(e) = app_handle.emit("catalog-status", &status) {
+                            eprintln!("Varo Error: Failed to emit catalog-status: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Varo Error: Failed to install the scanned catalog: {}", e),
+                }
+            });
 
             Ok(())
         })
         .plugin(tauri_plugin_opener::init())
+        // Node icons, referenced by the `icon` key of each node
+        .register_uri_scheme_protocol("varo-icon", |ctx, request| {
+            match ctx.app_handle().try_state::<VaroCore>() {
+                Some(core) => serve_icon(&core, &request),
+                // Not set up yet
+                None => Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(Vec::new())
+                    .unwrap_or_default(),
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             get_os_username,
             get_platform,
-            execute_program,
             get_env_presets,
             get_selected_env_preset,
             select_env_preset,
+            get_active_env_presets,
+            push_env_preset,
+            pop_env_preset,
+            reorder_env_presets,
+            create_env_preset,
+            update_env_preset,
+            duplicate_env_preset,
+            delete_env_preset,
             get_config,
             reload_config,
             get_nodes,
             execute_node,
+            get_diagnostics,
+            get_scan_stats,
+            get_catalog_status,
+            get_hidden_nodes,
+            list_running,
+            kill_launch,
+            wait_launch,
+            read_launch_log,
+            explain_node_access,
+            plan_node_launch,
+            export_env,
             show_node_in_folder,
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,119 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+/// A problem found while loading a node, preset or config file
+#[derive(Debug, Serialize, Clone)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub severity: Severity,
+    pub field: Option<String>,
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    pub fn new<T: Into<String>>(severity: Severity, file: Option<&str>, message: T) -> Self {
+        Self {
+            file: file.map(|f| f.to_string()),
+            severity,
+            field: None,
+            message: message.into(),
+            line: None,
+            column: None,
+        }
+    }
+
+    pub fn error<T: Into<String>>(file: Option<&str>, message: T) -> Self {
+        Self::new(Severity::Error, file, message)
+    }
+
+    pub fn warning<T: Into<String>>(file: Option<&str>, message: T) -> Self {
+        Self::new(Severity::Warning, file, message)
+    }
+
+    pub fn info<T: Into<String>>(file: Option<&str>, message: T) -> Self {
+        Self::new(Severity::Info, file, message)
+    }
+
+    /// Build an error from a serde_json parse failure, keeping its line and column
+    pub fn from_json_error(file: Option<&str>, err: &serde_json::Error) -> Self {
+        // serde_json appends "at line X column Y"; the location is stored separately
+        let message = err.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(index) => message[..index].to_string(),
+            None => message,
+        };
+        let mut diagnostic = Self::error(file, format!("Invalid JSON: {}", message));
+        if err.line() > 0 {
+            diagnostic.line = Some(err.line());
+            diagnostic.column = Some(err.column());
+        }
+        diagnostic
+    }
+
+    pub fn with_field<T: Into<String>>(mut self, field: T) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    pub fn with_location(mut self, location: Option<(usize, usize)>) -> Self {
+        if let Some((line, column)) = location {
+            self.line = Some(line);
+            self.column = Some(column);
+        }
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        };
+        write!(f, "{}: ", severity)?;
+        if let Some(file) = &self.file {
+            write!(f, "{}", file)?;
+            if let (Some(line), Some(column)) = (self.line, self.column) {
+                write!(f, ":{}:{}", line, column)?;
+            }
+            write!(f, ": ")?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, "'{}': ", field)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Find the 1-based line and column of the first `"key":` occurrence in JSON source text.
+/// Used to point field-level diagnostics at the right place in the file.
+pub fn locate_json_key(content: &str, key: &str) -> Option<(usize, usize)> {
+    let needle = format!("\"{}\"", key);
+    let mut search_from = 0;
+    while let Some(offset) = content[search_from..].find(&needle) {
+        let start = search_from + offset;
+        let rest = content[start + needle.len()..].trim_start();
+        if rest.starts_with(':') {
+            let line = content[..start].matches('\n').count() + 1;
+            let line_start = content[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let column = content[line_start..start].chars().count() + 1;
+            return Some((line, column));
+        }
+        search_from = start + needle.len();
+    }
+    None
+}
\ No newline at end of file
@@ -1,29 +1,32 @@
-use serde::{Serialize};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VaroNode {
     pub id: String,
     pub name: String,
     pub category: String,
     pub group_id: String,
-    pub icon: String, // raw embedded SVG or base64 PNG, or placeholder
+    pub icon: String, // key of the icon served at `varo-icon://localhost/<key>`, empty when it could not be loaded
+    #[serde(skip)]
+    pub icon_file: Option<String>, // resolved icon file, used to reload the node when the icon changes
     pub filepath: Option<String>,
     pub default_for_group: bool,
     pub description: Option<String>,
     pub status: Option<Status>,
     pub access: Option<Access>,
     pub commands: Vec<Command>,
+    pub cwd: Option<String>, // default working directory for commands without their own
     pub env: Vec<EnvVar>,
     pub date_modified: u64,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Status {
     pub name: String,
     pub color: String,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Access {
     pub platforms: Vec<String>,
     pub allow: Vec<String>,
@@ -31,10 +34,21 @@ pub struct Access {
 }
 
 #[derive(Debug, Serialize, Clone)]
+pub struct NodeAccessReport {
+    pub node_id: String,
+    pub node_name: String,
+    pub username: String,
+    pub platform: String,
+    pub allowed: bool,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Command {
     pub path: String,
     pub path_type: String,
-    pub args: String,
+    pub args: Vec<String>, // already split; ${VAR} tokens are expanded per argument at launch
+    pub cwd: Option<String>,
     pub wait: bool,
 }
 
@@ -42,18 +56,176 @@ fn default_wait() -> bool {
     true
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EnvVar {
     pub name: String,
     pub value: String,
     pub operation: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EnvPreset {
     pub id: String,
     pub name: String,
     pub filepath: Option<String>,
     pub description: Option<String>,
-    pub env: Vec<EnvVar>,
-}
\ No newline at end of file
+    pub extends: Vec<String>, // ids of parent presets, in merge order
+    pub provider: Option<EnvProvider>,
+    pub provided_env: Vec<EnvVar>, // entries produced by `provider`, applied before `env`
+    pub env: Vec<EnvVar>, // entries as written in the file
+    pub flattened_env: Vec<EnvVar>, // parent entries followed by `provided_env` and `env`; this is what gets applied
+}
+
+/// A command that prints environment variables for a preset, such as `rez env ... -- printenv`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EnvProvider {
+    pub command: String,
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+    pub format: String, // `auto`, `env` (KEY=VALUE lines) or `json`
+    pub timeout_secs: u64,
+    pub cache_ttl_secs: u64, // 0 disables caching
+    pub exclude: Vec<String>, // variables from the output to ignore
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LaunchState {
+    Running,
+    Exited,
+    Failed,
+    Killed,
+}
+
+/// A program started for a node command, tracked by the process registry
+#[derive(Debug, Serialize, Clone)]
+pub struct LaunchInfo {
+    pub launch_id: u64,
+    pub node_id: String,
+    pub preset_id: Option<String>, // top-most active preset
+    pub command_index: usize,
+    pub path: String,
+    pub pid: Option<u32>,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub state: LaunchState,
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    pub log_path: Option<String>,
+}
+/// Where a variable in a launch environment was changed
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvOrigin {
+    Preset,
+    Node,
+    Override,
+}
+
+/// A variable a launch sets or removes relative to the system environment
+#[derive(Debug, Serialize, Clone)]
+pub struct PlannedEnvVar {
+    pub name: String,
+    pub value: Option<String>, // None when the variable is removed
+    pub system_value: Option<String>,
+    pub origin: EnvOrigin,
+    pub preset_id: Option<String>, // last preset layer that changed it, when `origin` is preset
+}
+
+/// A node command after expansion and path resolution
+#[derive(Debug, Serialize, Clone)]
+pub struct PlannedCommand {
+    pub index: usize,
+    pub path_type: String,
+    pub path: String,
+    pub program: Option<String>, // resolved executable, None for URLs or when it cannot be found
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+    pub wait: bool,
+    pub unresolved: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// What launching a node would run, without starting anything
+#[derive(Debug, Serialize, Clone)]
+pub struct LaunchPlan {
+    pub node_id: String,
+    pub node_name: String,
+    pub preset_ids: Vec<String>, // active presets, bottom layer first
+    pub env: Vec<PlannedEnvVar>, // shared by all commands
+    pub errors: Vec<String>, // env problems that prevent launching
+    pub commands: Vec<PlannedCommand>,
+}
+
+/// Entries added, changed and removed by a reload
+#[derive(Debug, Serialize, Clone)]
+pub struct EntryDelta<T> {
+    pub added: Vec<T>,
+    pub changed: Vec<T>,
+    pub removed: Vec<String>, // ids
+}
+
+impl<T> Default for EntryDelta<T> {
+    fn default() -> Self {
+        Self { added: Vec::new(), changed: Vec::new(), removed: Vec::new() }
+    }
+}
+
+impl<T> EntryDelta<T> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// What changed after files were edited on disk, sent to the frontend with `catalog-changed`
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct CatalogDelta {
+    pub nodes: EntryDelta<VaroNode>, // visible nodes only
+    pub presets: EntryDelta<EnvPreset>,
+    pub active_preset_ids: Option<Vec<String>>, // new stack, when it changed
+    pub diagnostics_changed: bool,
+    pub config_changed: bool,
+}
+
+impl CatalogDelta {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+            && self.presets.is_empty()
+            && self.active_preset_ids.is_none()
+            && !self.diagnostics_changed
+            && !self.config_changed
+    }
+}
+
+/// How long the last node scan took and how many files it could reuse from earlier scans
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ScanStats {
+    pub varo_path: Option<String>,
+    pub files: usize,
+    pub reused: usize, // same size and modification time
+    pub unchanged: usize, // new modification time, same content hash
+    pub parsed: usize,
+    pub timed_out: usize, // still being read after `nodes.load_timeout_secs`
+    pub list_ms: u64, // reading the nodes folder
+    pub load_ms: u64, // checking, reading and parsing files on the loader threads
+    pub total_ms: u64, // including duplicate and reference checks
+    pub error: Option<String>, // why the nodes folder could not be read
+}
+
+/// Where the catalog comes from
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CatalogSource {
+    Disk,
+    Cache,
+}
+
+/// Whether the nodes and presets shown are fresh, sent to the frontend with `catalog-status`
+#[derive(Debug, Serialize, Clone)]
+pub struct CatalogStatus {
+    pub source: CatalogSource,
+    pub stale: bool, // loaded from the cache and not yet confirmed by a scan
+    pub refreshing: bool,
+    pub cache_saved_at: Option<u64>, // when the cache in use was written, in ms since the Unix epoch
+    pub error: Option<String>, // why the last scan could not read the catalog
+}
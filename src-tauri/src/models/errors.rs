@@ -10,6 +10,7 @@ pub enum VaroError {
     SerializationError(String),
     LockError(String),
     ExecutionError(String),
+    AccessDeniedError(String),
 }
 
 impl fmt::Display for VaroError {
@@ -23,6 +24,7 @@ impl fmt::Display for VaroError {
             VaroError::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
             VaroError::LockError(msg) => write!(f, "Lock error: {}", msg),
             VaroError::ExecutionError(msg) => write!(f, "Execution error: {}", msg),
+            VaroError::AccessDeniedError(msg) => write!(f, "Access denied: {}", msg),
         }
     }
 }
@@ -80,4 +82,8 @@ impl VaroError {
     pub fn lock<T: Into<String>>(msg: T) -> Self {
         VaroError::LockError(msg.into())
     }
+
+    pub fn access_denied<T: Into<String>>(msg: T) -> Self {
+        VaroError::AccessDeniedError(msg.into())
+    }
 }
\ No newline at end of file
@@ -1,2 +1,3 @@
+pub mod diagnostics;
 pub mod entities;
 pub mod errors;
\ No newline at end of file
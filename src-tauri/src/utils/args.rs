@@ -0,0 +1,75 @@
+/// Splits a command-line string into arguments using POSIX-style quoting rules.
+///
+/// - Unquoted whitespace separates arguments.
+/// - Single quotes keep everything up to the next single quote literally.
+/// - Double quotes group text; inside them `\"` and `\\` are escapes, any other
+///   backslash is kept so Windows paths like `"C:\Program Files\App"` survive.
+/// - Outside quotes a backslash escapes a following space, tab or quote. Other
+///   backslashes are kept as-is for the same reason.
+///
+/// `${VAR}` tokens are left untouched so they can be expanded per argument afterwards.
+pub fn split_command_line(input: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    // Tracks `""` and `''` so empty quoted arguments are kept
+    let mut in_arg = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(format!("Unterminated single quote in arguments: {}", input)),
+                    }
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.peek() {
+                            Some(&next) if next == '"' || next == '\\' => {
+                                current.push(next);
+                                chars.next();
+                            }
+                            _ => current.push('\\'),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(format!("Unterminated double quote in arguments: {}", input)),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                match chars.peek() {
+                    Some(&next) if next.is_whitespace() || next == '"' || next == '\'' => {
+                        current.push(next);
+                        chars.next();
+                    }
+                    _ => current.push('\\'),
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    Ok(args)
+}
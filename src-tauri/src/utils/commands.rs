@@ -1,84 +1,57 @@
+use std::path::Path;
 use std::process::{Command};
 use std::collections::HashMap;
+use crate::utils::log::varo_log;
 
-#[tauri::command]
-pub fn execute_program(
-    path: String, 
-    args: Option<Vec<String>>, 
+/// Builds a process Command with arguments and environment changes applied.
+/// `env_vars` are set on top of the inherited environment and `unset_vars` are removed from it.
+/// The program starts in `cwd` when given, otherwise in Varo's own working directory.
+pub fn build_command(
+    path: &str,
+    args: Option<Vec<String>>,
     env_vars: Option<HashMap<String, String>>,
-    wait: bool
-) -> Result<(), String> {
-    println!("[Execute Program] Starting execution:");
-    println!("[Execute Program]   Path: '{}'", path);
-    println!("[Execute Program]   Args: {:?}", args);
-    println!("[Execute Program]   Env vars: {:?}", env_vars);
-    println!("[Execute Program]   Wait: {}", wait);
-
+    unset_vars: Option<Vec<String>>,
+    cwd: Option<&Path>,
+) -> Command {
     // Check if the path exists
     let path_exists = std::path::Path::new(&path).exists();
-    println!("[Execute Program]   Path exists: {}", path_exists);
+    varo_log!("[Execute Program]   Path exists: {}", path_exists);
     
     if !path_exists {
-        println!("[Execute Program]   WARNING: Path does not exist on filesystem");
+        varo_log!("[Execute Program]   WARNING: Path does not exist on filesystem");
     }
 
-    let mut cmd = Command::new(&path);
-    println!("[Execute Program] Created Command for: {}", path);
+    let mut cmd = Command::new(path);
+    varo_log!("[Execute Program] Created Command for: {}", path);
     
     if let Some(arguments) = args {
-        println!("[Execute Program] Adding arguments: {:?}", arguments);
+        varo_log!("[Execute Program] Adding arguments: {:?}", arguments);
         cmd.args(arguments);
     } else {
-        println!("[Execute Program] No arguments to add");
+        varo_log!("[Execute Program] No arguments to add");
     }
 
     if let Some(env) = env_vars {
-        println!("[Execute Program] Adding environment variables:");
+        varo_log!("[Execute Program] Adding environment variables:");
         for (key, value) in &env {
-            println!("[Execute Program]   Setting {}={}", key, value);
+            varo_log!("[Execute Program]   Setting {}={}", key, value);
         }
         cmd.envs(env);
     } else {
-        println!("[Execute Program] No environment variables to add");
+        varo_log!("[Execute Program] No environment variables to add");
     }
 
-    println!("[Execute Program] About to execute command...");
-
-    if wait {
-        println!("[Execute Program] Executing in BLOCKING mode");
-        // Blocking
-        match cmd.status() {
-            Ok(status) => {
-                println!("[Execute Program] Command completed with status: {:?}", status);
-                if !status.success() {
-                    let error_msg = format!("Process exited with code: {:?}", status.code());
-                    println!("[Execute Program] Error: {}", error_msg);
-                    return Err(error_msg);
-                } else {
-                    println!("[Execute Program] Command executed successfully");
-                }
-            },
-            Err(e) => {
-                let error_msg = format!("Launch failed: {}", e);
-                println!("[Execute Program] Launch error: {}", error_msg);
-                return Err(error_msg);
-            }
-        }
-    } else {
-        println!("[Execute Program] Executing in NON-BLOCKING mode");
-        // Non-blocking
-        match cmd.spawn() {
-            Ok(child) => {
-                println!("[Execute Program] Process spawned successfully with PID: {}", child.id());
-            },
-            Err(e) => {
-                let error_msg = format!("Launch failed: {}", e);
-                println!("[Execute Program] Spawn error: {}", error_msg);
-                return Err(error_msg);
-            }
+    if let Some(unset) = unset_vars {
+        for key in &unset {
+            varo_log!("[Execute Program]   Removing {}", key);
+            cmd.env_remove(key);
         }
     }
 
-    println!("[Execute Program] Execution completed successfully");
-    Ok(())
+    if let Some(dir) = cwd {
+        varo_log!("[Execute Program] Working directory: {}", dir.display());
+        cmd.current_dir(dir);
+    }
+
+    cmd
 }
\ No newline at end of file
@@ -1,7 +1,9 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde_json::{Value, json};
 use crate::utils::env::expand_env_vars;
+use crate::utils::files::write_file_atomic;
+use crate::utils::log::varo_log;
 
 
 /// Get the path to the environment-specified config file
@@ -9,28 +11,40 @@ fn get_env_config_path() -> Option<PathBuf> {
     std::env::var("VARO_CONFIG_PATH").ok().map(PathBuf::from)
 }
 
+/// Get the user Varo folder (e.g. `~/AppData/Local/Varo`), creating it as needed
+pub fn get_user_varo_dir() -> Option<PathBuf> {
+    let mut varo_dir = dirs::config_local_dir()?;
+    varo_dir.push("Varo");
+
+    // Ensure the Varo/ folder exists
+    if std::fs::create_dir_all(&varo_dir).is_err() {
+        return None;
+    }
+
+    Some(varo_dir)
+}
+
 /// Get the path to the user-specific config file, creating directories as needed
 fn get_user_config_path() -> Option<PathBuf> {
-    if let Some(mut doc_path) = dirs::config_local_dir() {
-        doc_path.push("Varo");
-        
-        // Ensure the Varo/ folder exists
-        if std::fs::create_dir_all(&doc_path).is_err() {
-            return None;
-        }
-
-        let config_path = doc_path.join("config.json");
-        
-        // Create empty config file if it doesn't exist
-        if !config_path.exists() {
-            let _ = std::fs::write(&config_path, "{}");
-        }
-        
-        println!("[Config Utils] User config folder: {}", doc_path.display());
-        Some(config_path)
-    } else {
-        None
+    let doc_path = get_user_varo_dir()?;
+    let config_path = doc_path.join("config.json");
+    
+    // Create empty config file if it doesn't exist
+    if !config_path.exists() {
+        let _ = std::fs::write(&config_path, "{}");
     }
+    
+    varo_log!("[Config Utils] User config folder: {}", doc_path.display());
+    Some(config_path)
+}
+
+/// Get every config file merged into the configuration, lowest priority first:
+/// `VARO_CONFIG_PATH`, the user config and the command-line (`--config`) file
+pub fn get_config_file_paths(cli_config_path: Option<&Path>) -> Vec<PathBuf> {
+    get_env_config_path().into_iter()
+        .chain(get_user_config_path())
+        .chain(cli_config_path.map(Path::to_path_buf))
+        .collect()
 }
 
 /// Load and parse a JSON config file from the given path
@@ -41,6 +55,15 @@ fn load_config_file(path: PathBuf) -> Value {
         .unwrap_or_else(|| json!({}))
 }
 
+/// Load and parse a JSON config file that was explicitly requested (e.g. via `--config`).
+/// Unlike `load_config_file`, a missing or malformed file is reported as an error.
+pub fn load_required_config_file(path: &Path) -> Result<Value, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file '{}': {}", path.display(), e))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse config file '{}': {}", path.display(), e))
+}
+
 /// Recursively merge two JSON configurations, with overrides taking precedence
 pub fn merge_configs(base: &mut Value, overrides: &Value) {
     match (base, overrides) {
@@ -64,3 +87,36 @@ pub fn load_env_config() -> Option<Value> {
 pub fn load_user_config() -> Option<Value> {
     get_user_config_path().map(load_config_file)
 }
+
+/// Set a value in the user config file, creating it and any intermediate objects as needed.
+/// `key_path` is a list of nested keys, e.g. `["env_presets", "last_selected_id"]`.
+/// Other values already in the file are preserved. A file that cannot be read or parsed, or a
+/// key on the path that holds something other than an object, is reported and left untouched.
+pub fn save_user_config_value(key_path: &[&str], value: Value) -> Result<(), String> {
+    let path = get_user_config_path().ok_or_else(|| "User config path is not available".to_string())?;
+    let mut user_config = match fs::read_to_string(&path) {
+        Ok(contents) if contents.trim().is_empty() => json!({}),
+        Ok(contents) => serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse user config '{}': {}", path.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => json!({}),
+        Err(e) => return Err(format!("Failed to read user config '{}': {}", path.display(), e)),
+    };
+
+    let mut current = &mut user_config;
+    for (index, key) in key_path.iter().enumerate() {
+        let map = match current {
+            Value::Object(map) => map,
+            _ if index == 0 => return Err(format!("User config '{}' is not a JSON object", path.display())),
+            _ => return Err(format!("User config key '{}' is not an object", key_path[..index].join("."))),
+        };
+        if index == key_path.len() - 1 {
+            map.insert(key.to_string(), value);
+            break;
+        }
+        current = map.entry(key.to_string()).or_insert_with(|| json!({}));
+    }
+
+    let contents = serde_json::to_string_pretty(&user_config)
+        .map_err(|e| format!("Failed to serialize user config: {}", e))?;
+    write_file_atomic(&path, contents.as_bytes())
+}
@@ -0,0 +1,253 @@
+use crate::models::entities::EnvVar;
+
+/// Contents of a `.env` file or a shell script made of `export` lines
+#[derive(Debug, Default)]
+pub struct DotenvFile {
+    /// `# key: value` comments before the first entry, with lowercase keys
+    pub front_matter: Vec<(String, String)>,
+    /// Entries in file order. Values use Varo's `${VAR}` syntax, so `$HOME` becomes `${HOME}`
+    /// and literal `$` signs are written as `$$`.
+    pub entries: Vec<EnvVar>,
+    /// Lines that were skipped or only partly understood, as (line number, message)
+    pub problems: Vec<(usize, String)>,
+}
+
+impl DotenvFile {
+    /// First front-matter value for `key`
+    pub fn front_matter_value(&self, key: &str) -> Option<&str> {
+        self.front_matter.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    None,
+    Single,
+    Double,
+}
+
+/// Parses `KEY=VALUE` lines as found in `.env` files and simple shell scripts.
+///
+/// - Blank lines and `#` comments are skipped; `# key: value` comments before the first entry are front matter.
+/// - `export KEY=VALUE` and `unset KEY` are understood. `export KEY` without a value is ignored.
+/// - Values may be unquoted (ending at a ` #` comment), single-quoted (literal) or double-quoted
+///   (with `\n`, `\t`, `\"`, `\\` and `\$` escapes). Quoted values may span several lines.
+/// - `$VAR` and `${VAR}` references are kept for expansion, except inside single quotes.
+pub fn parse_dotenv(content: &str) -> DotenvFile {
+    let mut file = DotenvFile::default();
+    let lines: Vec<&str> = content.trim_start_matches('\u{feff}').lines().collect();
+    let mut in_front_matter = true;
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line_number = index + 1;
+        let line = lines[index].trim();
+        index += 1;
+
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            if in_front_matter {
+                if let Some((key, value)) = parse_front_matter(comment) {
+                    file.front_matter.push((key, value));
+                }
+            }
+            continue;
+        }
+        in_front_matter = false;
+
+        if let Some(names) = strip_keyword(line, "unset") {
+            for name in names.split_whitespace().filter(|name| !name.starts_with('-')) {
+                if is_valid_key(name) {
+                    file.entries.push(EnvVar { name: name.to_string(), value: String::new(), operation: Some("unset".to_string()) });
+                } else {
+                    file.problems.push((line_number, format!("Invalid variable name '{}' in unset", name)));
+                }
+            }
+            continue;
+        }
+
+        let assignment = strip_keyword(line, "export").unwrap_or(line);
+        let (key, raw_value) = match assignment.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim_start()),
+            None if assignment != line => continue, // `export NAME` marks an existing variable for export
+            None => {
+                file.problems.push((line_number, format!("Expected KEY=VALUE, skipping: {}", line)));
+                continue;
+            }
+        };
+        if !is_valid_key(key) {
+            file.problems.push((line_number, format!("Invalid variable name '{}', skipping line", key)));
+            continue;
+        }
+
+        let (quoting, text) = match raw_value.chars().next() {
+            Some('\'') => (Quoting::Single, &raw_value[1..]),
+            Some('"') => (Quoting::Double, &raw_value[1..]),
+            _ => (Quoting::None, raw_value),
+        };
+
+        let raw = if quoting == Quoting::None {
+            strip_inline_comment(text).trim_end().to_string()
+        } else {
+            // Keep reading lines until the closing quote
+            let quote = if quoting == Quoting::Single { '\'' } else { '"' };
+            let mut raw = text.to_string();
+            loop {
+                if let Some(end) = find_closing_quote(&raw, quote) {
+                    let rest = raw[end + 1..].trim();
+                    if !rest.is_empty() && !rest.starts_with('#') {
+                        file.problems.push((line_number, format!("Ignoring text after the closing quote of '{}': {}", key, rest)));
+                    }
+                    raw.truncate(end);
+                    break;
+                }
+                match lines.get(index) {
+                    Some(next) => {
+                        raw.push('\n');
+                        raw.push_str(next);
+                        index += 1;
+                    }
+                    None => {
+                        file.problems.push((line_number, format!("Unterminated quote in value of '{}'", key)));
+                        break;
+                    }
+                }
+            }
+            raw
+        };
+
+        let (value, warning) = to_template(&raw, quoting);
+        if let Some(warning) = warning {
+            file.problems.push((line_number, format!("{} (in '{}')", warning, key)));
+        }
+        file.entries.push(EnvVar { name: key.to_string(), value, operation: Some("set".to_string()) });
+    }
+
+    file
+}
+
+/// `key: value` inside a comment, where the key is a single word
+fn parse_front_matter(comment: &str) -> Option<(String, String)> {
+    let (key, value) = comment.split_once(':')?;
+    let key = key.trim();
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return None;
+    }
+    Some((key.to_lowercase(), value.trim().to_string()))
+}
+
+/// The rest of `line` after a leading shell keyword such as `export`
+fn strip_keyword<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(keyword)?;
+    if rest.starts_with(|c: char| c.is_whitespace()) {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Cuts an unquoted value at a `#` that follows whitespace
+fn strip_inline_comment(text: &str) -> &str {
+    let mut previous_is_space = false;
+    for (index, c) in text.char_indices() {
+        if c == '#' && previous_is_space {
+            return &text[..index];
+        }
+        previous_is_space = c.is_whitespace();
+    }
+    text
+}
+
+/// Byte index of the closing quote, skipping backslash-escaped quotes inside double quotes
+fn find_closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// Converts shell value text to Varo's expansion syntax, returning a warning for unsupported constructs
+fn to_template(raw: &str, quoting: Quoting) -> (String, Option<&'static str>) {
+    let chars: Vec<char> = raw.chars().collect();
+    let mut out = String::with_capacity(raw.len());
+    let mut warning = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '$' if quoting == Quoting::Single => out.push_str("$$"),
+            '\\' if quoting != Quoting::Single && i + 1 < chars.len() => {
+                i += 1;
+                match (quoting, chars[i]) {
+                    (_, '$') => out.push_str("$$"),
+                    (Quoting::Double, 'n') => out.push('\n'),
+                    (Quoting::Double, 't') => out.push('\t'),
+                    (Quoting::Double, 'r') => out.push('\r'),
+                    (Quoting::Double, escaped @ ('"' | '\\' | '`')) => out.push(escaped),
+                    (Quoting::Double, other) => {
+                        out.push('\\');
+                        out.push(other);
+                    }
+                    (_, other) => out.push(other),
+                }
+            }
+            '$' => match chars.get(i + 1) {
+                Some('{') => {
+                    // Copy `${...}` through as-is, including nested braces
+                    let mut depth = 0;
+                    let end = (i + 1..chars.len()).find(|&j| {
+                        match chars[j] {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                        depth == 0
+                    });
+                    match end {
+                        Some(end) => {
+                            out.extend(&chars[i..=end]);
+                            i = end;
+                        }
+                        None => out.push_str("$$"),
+                    }
+                }
+                Some(&next) if next.is_ascii_alphabetic() || next == '_' => {
+                    let end = (i + 1..chars.len())
+                        .find(|&j| !(chars[j].is_ascii_alphanumeric() || chars[j] == '_'))
+                        .unwrap_or(chars.len());
+                    out.push_str("${");
+                    out.extend(&chars[i + 1..end]);
+                    out.push('}');
+                    i = end - 1;
+                }
+                Some('(') => {
+                    warning = Some("Command substitution is not supported, `$(` is kept as text");
+                    out.push_str("$$");
+                }
+                _ => out.push_str("$$"),
+            },
+            other => out.push(other),
+        }
+        i += 1;
+    }
+
+    (out, warning)
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use crate::models::diagnostics::Diagnostic;
+
+/// Removes items whose id is already used by an earlier item, so the first one in scan order wins.
+/// `identify` returns an item's id and the file it was loaded from. Every dropped item is reported
+/// in `diagnostics` with both file paths. `kind` names the items in messages, e.g. "preset".
+pub fn remove_duplicate_ids<T, F>(items: &mut Vec<T>, kind: &str, identify: F, diagnostics: &mut Vec<Diagnostic>)
+where
+    F: Fn(&T) -> (&str, Option<&str>),
+{
+    let mut first_files: HashMap<String, Option<String>> = HashMap::new();
+
+    items.retain(|item| {
+        let (id, file) = identify(item);
+        match first_files.get(id) {
+            None => {
+                first_files.insert(id.to_string(), file.map(|f| f.to_string()));
+                true
+            }
+            Some(kept_file) => {
+                let message = format!(
+                    "Duplicate {} id '{}': already defined in {}, which takes precedence. This file is ignored.",
+                    kind,
+                    id,
+                    kept_file.as_deref().unwrap_or("another definition")
+                );
+                diagnostics.push(Diagnostic::error(file, message).with_field("id"));
+                false
+            }
+        }
+    });
+}
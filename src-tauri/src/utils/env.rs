@@ -3,45 +3,145 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 use serde_json::Value;
-use crate::models::entities::{EnvVar, EnvPreset};
+use crate::models::entities::{EnvVar, EnvPreset, EnvProvider};
+use crate::models::diagnostics::{Diagnostic, locate_json_key};
+use crate::utils::args::split_command_line;
+use crate::utils::dotenv::parse_dotenv;
+use crate::utils::expand::{expand, resolve_env_layer, ExpandContext, Expanded, ExpansionIssue};
 use crate::utils::hasher::Hasher;
+use crate::utils::platform;
 
 /// Returns current list of environment variables
 pub fn get_current_env_vars() -> HashMap<String, String> {
     env::vars().collect()
 }
 
-/// Returns a combined environment map with preset variables taking precedence over system variables
-pub fn get_env_vars_with_preset(preset: Option<&EnvPreset>) -> HashMap<String, String> {
+/// Returns a combined environment map with the presets applied in order on top of the system variables.
+/// Each preset layer can reference variables set by the layers below it.
+pub fn get_env_vars_with_presets(presets: &[EnvPreset]) -> HashMap<String, String> {
     let mut env_map = get_current_env_vars();
     
-    // Add preset environment variables if available (these override system vars)
-    if let Some(preset) = preset {
-        for env_var in &preset.env {
-            // Expand tokens in the preset variable value using the current environment
-            let expanded_value = expand_tokens_with_map(&env_var.value, &env_map);
-            env_map.insert(env_var.name.clone(), expanded_value);
-        }
+    // Apply preset environment variables layer by layer (these override system vars)
+    for preset in presets {
+        let context = ExpandContext { preset_id: Some(preset.id.clone()), ..Default::default() };
+        apply_env_vars(&mut env_map, &preset.flattened_env, &context);
     }
     
     env_map
 }
 
+/// Applies a list of EnvVar entries to the environment map.
+/// References between entries are resolved in dependency order, and `${NAME}` inside an entry
+/// for `NAME` refers to the value before that entry (e.g. `PATH=${PATH}:/tools`).
+/// Built-in tokens such as `${node.id}` are taken from `context`.
+/// See [`resolve_env_layer`] for details; problems are returned so callers can report them.
+pub fn apply_env_vars(env_map: &mut HashMap<String, String>, env_vars: &[EnvVar], context: &ExpandContext) -> Vec<ExpansionIssue> {
+    resolve_env_layer(env_map, env_vars, context)
+}
+
+/// Operations supported on EnvVar entries
+pub const ENV_OPERATIONS: [&str; 4] = ["set", "append", "prepend", "unset"];
+
+/// Applies a single `set`, `append`, `prepend` or `unset` operation to the environment map.
+/// `append` and `prepend` join entries with the platform path separator and move entries
+/// that are already present in the existing value to the end or front.
+pub fn apply_env_operation(env_map: &mut HashMap<String, String>, name: &str, value: &str, operation: &str) {
+    match operation.to_lowercase().as_str() {
+        "set" => {
+            env_map.insert(name.to_string(), value.to_string());
+        }
+        "append" | "prepend" => {
+            let existing = env_map.get(name).map(String::as_str).unwrap_or("");
+            let combined = join_path_list(existing, value, operation.eq_ignore_ascii_case("prepend"));
+            env_map.insert(name.to_string(), combined);
+        }
+        "unset" => {
+            env_map.remove(name);
+        }
+        other => {
+            eprintln!("Warning: Unknown env operation '{}' for '{}', falling back to 'set'", other, name);
+            env_map.insert(name.to_string(), value.to_string());
+        }
+    }
+}
+
+/// Joins new path-list entries onto an existing value, dropping empty entries. An entry that is
+/// already present is moved to the front (or back) rather than added a second time.
+fn join_path_list(existing: &str, value: &str, prepend: bool) -> String {
+    let separator = platform::get_path_separator();
+
+    let mut new_entries: Vec<&str> = Vec::new();
+    for entry in value.split(separator).filter(|e| !e.is_empty()) {
+        if !new_entries.iter().any(|e| path_entries_equal(e, entry)) {
+            new_entries.push(entry);
+        }
+    }
+    let existing_entries: Vec<&str> = existing.split(separator)
+        .filter(|e| !e.is_empty())
+        .filter(|e| !new_entries.iter().any(|new| path_entries_equal(new, e)))
+        .collect();
+
+    let entries: Vec<&str> = if prepend {
+        new_entries.into_iter().chain(existing_entries).collect()
+    } else {
+        existing_entries.into_iter().chain(new_entries).collect()
+    };
+    entries.join(separator)
+}
+
+/// Compares two path-list entries, ignoring case and trailing slashes on Windows
+fn path_entries_equal(a: &str, b: &str) -> bool {
+    if cfg!(target_os = "windows") {
+        let normalize = |s: &str| s.replace('\\', "/").trim_end_matches('/').to_lowercase();
+        normalize(a) == normalize(b)
+    } else {
+        a.trim_end_matches('/') == b.trim_end_matches('/')
+    }
+}
+
+/// Compares a resolved environment against the system environment.
+/// Returns the variables that were added or changed, and the names of variables that were removed.
+pub fn diff_env_against_system(
+    env_map: &HashMap<String, String>,
+    system_env: &HashMap<String, String>,
+) -> (HashMap<String, String>, Vec<String>) {
+    let changed = env_map.iter()
+        .filter(|(key, value)| system_env.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    let mut removed: Vec<String> = system_env.keys()
+        .filter(|key| !env_map.contains_key(*key))
+        .cloned()
+        .collect();
+    removed.sort();
+
+    (changed, removed)
+}
+
 /// Expands `${KEY}` placeholders in a string using values from the provided `vars` map.
-/// Returns the updated string with tokens replaced.
+/// Expansion is a single pass: inserted values are not expanded again, and unknown keys are left as written.
+/// Supports `${KEY:-fallback}`, `${KEY:?message}`, `$$` and the built-in tokens that need no node or preset.
 pub fn expand_tokens_with_map(input: &str, vars: &HashMap<String, String>) -> String {
-    let mut output = input.to_string();
-    for (key, value) in vars {
-        output = output.replace(&format!("${{{}}}", key), value);
-    }
-    output
+    expand_template(input, vars, &ExpandContext::default()).value
+}
+
+/// Expands a template against `vars` and the built-in tokens in `context`,
+/// returning undefined references and failed `${KEY:?message}` tokens along with the value
+pub fn expand_template(input: &str, vars: &HashMap<String, String>, context: &ExpandContext) -> Expanded {
+    expand(input, context, &mut |key| vars.get(key).cloned())
 }
 
 /// Expands environment variables in a string using the current process environment.
 /// Replaces placeholders like `${VAR_NAME}` with their actual values.
 pub fn expand_env_vars(input: &str) -> String {
-    let env_map: HashMap<String, String> = env::vars().collect();
-    expand_tokens_with_map(input, &env_map)
+    expand_env_vars_with_context(input, &ExpandContext::default())
+}
+
+/// Expands a template against the current process environment and the built-in tokens in `context`.
+/// Looks variables up one at a time instead of copying the whole environment.
+pub fn expand_env_vars_with_context(input: &str, context: &ExpandContext) -> String {
+    expand(input, context, &mut |key| env::var(key).ok()).value
 }
 
 /// Recursively expands environment variables in JSON values in place with custom environment map
@@ -72,7 +172,8 @@ pub fn resolve_env_vars_recursive(value: &mut Value) {
 }
 
 /// Parses a JSON array into a list of EnvVar objects.
-/// Applies environment variable expansion to each field and uses "set" as the default operation.
+/// Names and operations are expanded against the process environment; values are kept as written
+/// and expanded when the entries are applied. Uses "set" as the default operation.
 pub fn parse_env_vars_from_json(env_array: &serde_json::Value) -> Vec<EnvVar> {
     env_array
         .as_array()
@@ -81,7 +182,7 @@ pub fn parse_env_vars_from_json(env_array: &serde_json::Value) -> Vec<EnvVar> {
                 .filter_map(|item| item.as_object())
                 .map(|env_obj| {
                     let name = env_obj.get("name").and_then(|v| v.as_str()).map(expand_env_vars).unwrap_or_default();
-                    let value = env_obj.get("value").and_then(|v| v.as_str()).map(expand_env_vars).unwrap_or_default();
+                    let value = env_obj.get("value").and_then(|v| v.as_str()).map(|s| s.to_string()).unwrap_or_default();
                     let operation = env_obj.get("operation")
                         .and_then(|v| v.as_str())
                         .map(expand_env_vars)
@@ -98,35 +199,311 @@ pub fn parse_env_vars_from_json(env_array: &serde_json::Value) -> Vec<EnvVar> {
         .unwrap_or_default()
 }
 
+/// Checks parsed EnvVar entries for empty names and unknown operations.
+/// References are checked by [`check_env_references`] once the surrounding environment is known.
+pub fn validate_env_vars(env_vars: &[EnvVar], path: &Path, diagnostics: &mut Vec<Diagnostic>) {
+    for (index, env_var) in env_vars.iter().enumerate() {
+        if env_var.name.trim().is_empty() {
+            diagnostics.push(Diagnostic::warning(path.to_str(), format!("Env entry {} has no name", index))
+                .with_field("env"));
+        }
+
+        let operation = env_var.operation.as_deref().unwrap_or("set").to_lowercase();
+        if !ENV_OPERATIONS.contains(&operation.as_str()) {
+            diagnostics.push(Diagnostic::warning(
+                path.to_str(),
+                format!("Env entry '{}' has unknown operation '{}', it will be treated as 'set'", env_var.name, operation),
+            ).with_field("env"));
+        }
+    }
+}
+
+/// Reports reference cycles between `env_vars` (errors), references to variables that are neither
+/// defined by `env_vars` nor present in `base_env`, and `${NAME:?message}` tokens that would fail (warnings)
+pub fn check_env_references(
+    env_vars: &[EnvVar],
+    base_env: &HashMap<String, String>,
+    context: &ExpandContext,
+    file: Option<&str>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut env_map = base_env.clone();
+    for issue in resolve_env_layer(&mut env_map, env_vars, context) {
+        let diagnostic = match issue {
+            ExpansionIssue::Cycle(_) => Diagnostic::error(file, issue.message()),
+            ExpansionIssue::Undefined { .. } | ExpansionIssue::Failed { .. } => Diagnostic::warning(file, issue.message()),
+        };
+        diagnostics.push(diagnostic.with_field("env"));
+    }
+}
+
 /// Loads a single EnvPreset from a JSON file.
 /// Parses fields like name, description, and env variables.
-/// If "id" is missing from the file, a fallback ID is generated from the file path.
-pub fn load_env_preset_from_file(path: &PathBuf) -> Option<EnvPreset> {
-    let content = fs::read_to_string(path).ok()?;
-    let json: Value = serde_json::from_str(&content).ok()?;
+/// If "id" is missing from the file, a fallback ID is generated from the file's path relative to `root`,
+/// the folder containing the preset directory, so the directory's name is part of it.
+/// Problems are recorded in `diagnostics`; returns `None` if the preset cannot be used.
+pub fn load_env_preset_from_file(path: &PathBuf, root: &Path, diagnostics: &mut Vec<Diagnostic>) -> Option<EnvPreset> {
+    match fs::read_to_string(path) {
+        Ok(content) => parse_env_preset_json(&content, path, root, diagnostics),
+        Err(e) => {
+            diagnostics.push(Diagnostic::error(path.to_str(), format!("Failed to read file: {}", e)));
+            None
+        }
+    }
+}
+
+/// Parses the JSON text of a preset file as if it had been read from `path`.
+/// Used by [`load_env_preset_from_file`] and to check edited presets before they are written.
+/// `root` is used for the fallback ID as in [`load_env_preset_from_file`].
+pub fn parse_env_preset_json(content: &str, path: &PathBuf, root: &Path, diagnostics: &mut Vec<Diagnostic>) -> Option<EnvPreset> {
+    let file = path.to_str();
+
+    let json: Value = match serde_json::from_str(content) {
+        Ok(json) => json,
+        Err(e) => {
+            diagnostics.push(Diagnostic::from_json_error(file, &e));
+            return None;
+        }
+    };
+
+    let name = match json.get("name").and_then(|v| v.as_str()) {
+        Some(name) => name.to_string(),
+        None => {
+            diagnostics.push(Diagnostic::error(file, "Missing or invalid 'name' field")
+                .with_field("name")
+                .with_location(locate_json_key(content, "name")));
+            return None;
+        }
+    };
 
-    let name = json.get("name").and_then(|v| v.as_str())?.to_string();
     let description = json.get("description").and_then(|v| v.as_str()).map(|s| s.to_string());
     let env = json.get("env").map(parse_env_vars_from_json).unwrap_or_default();
+    validate_env_vars(&env, path, diagnostics);
     let filepath = path.to_str().map(|s| s.to_string());
     let id = json.get("id")
         .and_then(|v| v.as_str())
         .map(|s| s.to_string())
-        .unwrap_or_else(|| Hasher::generate_id_from_path(path));
+        .unwrap_or_else(|| Hasher::generate_id_from_path(path, root));
+    let extends = match json.get("extends") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::String(parent)) => vec![parent.clone()],
+        Some(Value::Array(parents)) => parents.iter()
+            .filter_map(|parent| match parent.as_str() {
+                Some(parent) => Some(parent.to_string()),
+                None => {
+                    diagnostics.push(Diagnostic::warning(file, format!("'extends' entries must be preset ids, skipping: {}", parent))
+                        .with_field("extends")
+                        .with_location(locate_json_key(content, "extends")));
+                    None
+                }
+            })
+            .collect(),
+        Some(other) => {
+            diagnostics.push(Diagnostic::warning(file, format!("'extends' must be a preset id or an array of ids, found: {}", other))
+                .with_field("extends")
+                .with_location(locate_json_key(content, "extends")));
+            Vec::new()
+        }
+    };
+
+    let provider = match json.get("provider") {
+        None | Some(Value::Null) => None,
+        Some(provider_json) => match parse_provider_from_json(provider_json) {
+            Ok(provider) => Some(provider),
+            Err(e) => {
+                // Without its provider the preset would be missing part of its environment
+                diagnostics.push(Diagnostic::error(file, e)
+                    .with_field("provider")
+                    .with_location(locate_json_key(content, "provider")));
+                return None;
+            }
+        },
+    };
+
+    // Provider output and parents are merged in by the preset manager once every directory has been scanned
+    let flattened_env = env.clone();
 
     Some(EnvPreset {
         id,
         name,
         filepath,
         description,
+        extends,
+        provider,
+        provided_env: Vec::new(),
         env,
+        flattened_env,
     })
 }
 
-/// Loads all valid EnvPreset JSON files in the specified directory.
-/// Only files with a `.json` extension are considered.
-pub fn load_env_presets_in_dir(dir_path: &str) -> Result<Vec<EnvPreset>, String> {
-    let mut presets = Vec::new();
+/// Output formats understood for preset `provider` commands
+pub const PROVIDER_FORMATS: [&str; 3] = ["auto", "env", "json"];
+
+/// Parses a preset `provider` block.
+/// `command`, `args` and `cwd` are expanded against the process environment.
+pub fn parse_provider_from_json(provider_json: &Value) -> Result<EnvProvider, String> {
+    let obj = provider_json.as_object()
+        .ok_or_else(|| format!("'provider' must be an object, found: {}", provider_json))?;
+
+    let command = obj.get("command")
+        .and_then(|v| v.as_str())
+        .map(expand_env_vars)
+        .filter(|c| !c.trim().is_empty())
+        .ok_or("'provider' needs a 'command'")?;
+
+    let args = match obj.get("args") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::String(line)) => split_command_line(line)
+            .map_err(|e| format!("Invalid provider 'args': {}", e))?
+            .iter()
+            .map(|arg| expand_env_vars(arg))
+            .collect(),
+        Some(Value::Array(items)) => items.iter()
+            .map(|item| item.as_str().map(expand_env_vars).ok_or_else(|| format!("Provider 'args' entries must be strings, found: {}", item)))
+            .collect::<Result<_, _>>()?,
+        Some(other) => return Err(format!("Provider 'args' must be a string or an array, found: {}", other)),
+    };
+
+    let format = obj.get("format").and_then(|v| v.as_str()).unwrap_or("auto").to_lowercase();
+    if !PROVIDER_FORMATS.contains(&format.as_str()) {
+        return Err(format!("Unknown provider format '{}' (expected one of: {})", format, PROVIDER_FORMATS.join(", ")));
+    }
+
+    let seconds = |key: &str, default: u64| -> Result<u64, String> {
+        match obj.get(key) {
+            None | Some(Value::Null) => Ok(default),
+            Some(value) => value.as_u64().ok_or_else(|| format!("Provider '{}' must be a whole number of seconds, found: {}", key, value)),
+        }
+    };
+
+    Ok(EnvProvider {
+        command,
+        args,
+        cwd: obj.get("cwd").and_then(|v| v.as_str()).map(expand_env_vars).filter(|c| !c.is_empty()),
+        format,
+        timeout_secs: seconds("timeout_secs", 60)?,
+        cache_ttl_secs: seconds("cache_ttl_secs", 3600)?,
+        exclude: obj.get("exclude")
+            .and_then(|v| v.as_array())
+            .map(|names| names.iter().filter_map(|n| n.as_str()).map(|n| n.to_string()).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// Loads an EnvPreset from a `.env` file or a shell script of `export` lines.
+/// `name`, `id`, `description` and `extends` come from `# key: value` comments at the top of the file.
+/// Without them the name is the file name and the ID is generated from the file's path relative to `root`,
+/// the folder containing the preset directory.
+/// Lines that cannot be parsed are skipped and reported in `diagnostics`.
+/// `.sh` files that are neither plain variable lists nor have a `name` or `id` are not presets and return `None`.
+pub fn load_env_preset_from_dotenv(path: &PathBuf, root: &Path, diagnostics: &mut Vec<Diagnostic>) -> Option<EnvPreset> {
+    let file = path.to_str();
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            diagnostics.push(Diagnostic::error(file, format!("Failed to read file: {}", e)));
+            return None;
+        }
+    };
+
+    let parsed = parse_dotenv(&content);
+
+    // A `.sh` file is only a preset when it declares itself with front matter or contains nothing but
+    // variable lines, so helper scripts (e.g. provider commands) can live next to presets
+    let is_script = path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("sh"));
+    let has_front_matter = parsed.front_matter_value("name").is_some() || parsed.front_matter_value("id").is_some();
+    if is_script && !has_front_matter && (parsed.entries.is_empty() || !parsed.problems.is_empty()) {
+        return None;
+    }
+
+    for (line, message) in &parsed.problems {
+        diagnostics.push(Diagnostic::warning(file, message.clone())
+            .with_field("env")
+            .with_location(Some((*line, 1))));
+    }
+
+    let env = parsed.entries.clone();
+    validate_env_vars(&env, path, diagnostics);
+
+    let front_matter = |key: &str| parsed.front_matter_value(key).filter(|v| !v.is_empty());
+    let name = front_matter("name")
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| {
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            let name = file_name.trim_start_matches('.');
+            name.rsplit_once('.').map_or(name, |(stem, _)| stem).to_string()
+        });
+    let id = front_matter("id")
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| Hasher::generate_id_from_path(path, root));
+    let description = front_matter("description").map(|s| s.to_string());
+    let extends = front_matter("extends")
+        .map(|parents| parents.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|parent| !parent.is_empty())
+            .map(|parent| parent.to_string())
+            .collect())
+        .unwrap_or_default();
+
+    let flattened_env = env.clone();
+
+    Some(EnvPreset {
+        id,
+        name,
+        filepath: path.to_str().map(|s| s.to_string()),
+        description,
+        extends,
+        provider: None,
+        provided_env: Vec::new(),
+        env,
+        flattened_env,
+    })
+}
+
+/// File formats presets are loaded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PresetFileKind {
+    Json,
+    /// `.env` files (including a file named just `.env`) and `.sh` scripts of `export` lines
+    Dotenv,
+}
+
+fn preset_file_kind(path: &Path) -> Option<PresetFileKind> {
+    if path.file_name().and_then(|n| n.to_str()) == Some(".env") {
+        return Some(PresetFileKind::Dotenv);
+    }
+    match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "json" => Some(PresetFileKind::Json),
+        "env" | "sh" => Some(PresetFileKind::Dotenv),
+        _ => None,
+    }
+}
+
+/// True when `path` has a name presets are loaded from (`.json`, `.env` or `.sh`).
+/// Only the name is checked, so this also works for files that were just removed.
+pub fn is_preset_file_name(path: &Path) -> bool {
+    preset_file_kind(path).is_some()
+}
+
+/// Loads the preset in `path` according to its file type; fallback IDs are relative to `root`, the folder
+/// containing the preset directory (see [`preset_id_root`]).
+/// Returns `None` for files that are not presets or cannot be used, with problems recorded in `diagnostics`.
+pub fn load_env_preset_file(path: &PathBuf, root: &Path, diagnostics: &mut Vec<Diagnostic>) -> Option<EnvPreset> {
+    match preset_file_kind(path)? {
+        PresetFileKind::Json => load_env_preset_from_file(path, root, diagnostics),
+        PresetFileKind::Dotenv => load_env_preset_from_dotenv(path, root, diagnostics),
+    }
+}
+
+/// Folder fallback preset IDs are relative to: the parent of the preset directory `path` lies in.
+/// Preset directories are not scanned recursively, so this keeps the directory's name in the ID.
+pub fn preset_id_root(path: &Path) -> &Path {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    dir.parent().unwrap_or(dir)
+}
+
+/// Preset files in a directory, sorted by path so duplicate ids resolve the same way on every scan
+pub fn list_preset_files(dir_path: &str) -> Result<Vec<PathBuf>, String> {
     let dir = Path::new(dir_path);
 
     if !dir.exists() || !dir.is_dir() {
@@ -136,18 +513,10 @@ pub fn load_env_presets_in_dir(dir_path: &str) -> Result<Vec<EnvPreset>, String>
     let entries = std::fs::read_dir(dir)
         .map_err(|e| format!("Failed to read directory '{}': {}", dir_path, e))?;
 
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if path.extension()
-            .and_then(|e| e.to_str())
-            .map(|ext| ext.eq_ignore_ascii_case("json"))
-            .unwrap_or(false)
-        {
-            if let Some(preset) = load_env_preset_from_file(&path) {
-                presets.push(preset);
-            }
-        }
-    }
-
-    Ok(presets)
+    let mut paths: Vec<PathBuf> = entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_preset_file_name(path))
+        .collect();
+    paths.sort();
+    Ok(paths)
 }
@@ -0,0 +1,408 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use crate::models::entities::EnvVar;
+use crate::utils::env::apply_env_operation;
+use crate::utils::platform;
+
+/// A piece of a `${VAR}` template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Token(Token<'a>),
+}
+
+/// A `${...}` reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    /// The whole token as written, kept in the output when it cannot be expanded
+    pub raw: &'a str,
+    pub name: &'a str,
+    pub modifier: Modifier<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Modifier<'a> {
+    None,
+    /// `${NAME:-fallback}`: used when NAME is unset or empty. The fallback is expanded too.
+    Default(&'a str),
+    /// `${NAME:?message}`: expansion fails with `message` when NAME is unset or empty
+    Required(&'a str),
+    /// `${name:argument}`: argument for a built-in token, e.g. the format in `${date:%Y%m%d}`
+    Argument(&'a str),
+}
+
+/// Values for built-in tokens. Fields left as `None` are treated as unset.
+#[derive(Debug, Clone, Default)]
+pub struct ExpandContext {
+    /// `${varo.path}`; falls back to the `VARO_PATH` variable when not set
+    pub varo_path: Option<String>,
+    /// `${node.dir}`, the folder containing the node file
+    pub node_dir: Option<String>,
+    /// `${node.id}`
+    pub node_id: Option<String>,
+    /// `${preset.id}`
+    pub preset_id: Option<String>,
+}
+
+/// Result of expanding a template
+#[derive(Debug, Clone, Default)]
+pub struct Expanded {
+    pub value: String,
+    /// Names referenced without a fallback that had no value
+    pub undefined: Vec<String>,
+    /// Messages from failed `${NAME:?message}` tokens and invalid built-ins
+    pub errors: Vec<String>,
+}
+
+/// Splits a template into literal text and `${...}` tokens.
+/// `$$` is a literal `$`; an unterminated `${` and `${}` are kept as text.
+pub fn tokenize(input: &str) -> Vec<Segment<'_>> {
+    let bytes = input.as_bytes();
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+
+    while i + 1 < bytes.len() {
+        if bytes[i] != b'$' {
+            i += 1;
+            continue;
+        }
+
+        if bytes[i + 1] == b'$' {
+            if text_start < i {
+                segments.push(Segment::Text(&input[text_start..i]));
+            }
+            segments.push(Segment::Text(&input[i + 1..i + 2]));
+            i += 2;
+            text_start = i;
+            continue;
+        }
+
+        if bytes[i + 1] == b'{' {
+            // Find the matching brace so fallbacks can contain tokens: ${A:-${B}}
+            let mut depth = 0;
+            let mut close = None;
+            for (j, &b) in bytes.iter().enumerate().skip(i + 1) {
+                match b {
+                    b'{' => depth += 1,
+                    b'}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            close = Some(j);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some(end) = close {
+                if let Some(token) = parse_token(&input[i..=end], &input[i + 2..end]) {
+                    if text_start < i {
+                        segments.push(Segment::Text(&input[text_start..i]));
+                    }
+                    segments.push(Segment::Token(token));
+                    text_start = end + 1;
+                }
+                i = end + 1;
+                continue;
+            }
+            break;
+        }
+
+        i += 1;
+    }
+
+    if text_start < input.len() {
+        segments.push(Segment::Text(&input[text_start..]));
+    }
+    segments
+}
+
+fn parse_token<'a>(raw: &'a str, inner: &'a str) -> Option<Token<'a>> {
+    let (name, modifier) = match inner.split_once(':') {
+        Some((name, rest)) => {
+            let modifier = if let Some(fallback) = rest.strip_prefix('-') {
+                Modifier::Default(fallback)
+            } else if let Some(message) = rest.strip_prefix('?') {
+                Modifier::Required(message)
+            } else {
+                Modifier::Argument(rest)
+            };
+            (name, modifier)
+        }
+        None => (inner, Modifier::None),
+    };
+
+    if name.trim().is_empty() {
+        return None;
+    }
+    Some(Token { raw, name, modifier })
+}
+
+/// Names referenced by a template, including inside fallbacks, in order of first appearance
+pub fn references(input: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    collect_references(input, &mut names);
+    names
+}
+
+fn collect_references<'a>(input: &'a str, names: &mut Vec<&'a str>) {
+    for segment in tokenize(input) {
+        if let Segment::Token(token) = segment {
+            if !names.contains(&token.name) {
+                names.push(token.name);
+            }
+            if let Modifier::Default(fallback) = token.modifier {
+                collect_references(fallback, names);
+            }
+        }
+    }
+}
+
+/// Expands a template in a single pass. Built-in tokens are taken from `context`, anything else from
+/// `lookup`. Inserted values are never expanded again; tokens that cannot be expanded are kept as written.
+pub fn expand<F>(input: &str, context: &ExpandContext, lookup: &mut F) -> Expanded
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut result = Expanded { value: String::with_capacity(input.len()), ..Default::default() };
+    expand_into(input, context, lookup, &mut result);
+    result
+}
+
+fn expand_into<F>(input: &str, context: &ExpandContext, lookup: &mut F, result: &mut Expanded)
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    for segment in tokenize(input) {
+        let token = match segment {
+            Segment::Text(text) => {
+                result.value.push_str(text);
+                continue;
+            }
+            Segment::Token(token) => token,
+        };
+
+        let argument = match token.modifier {
+            Modifier::Argument(argument) => Some(argument),
+            _ => None,
+        };
+        let value = match builtin_value(token.name, argument, context, lookup) {
+            Some(Ok(value)) => value,
+            Some(Err(e)) => {
+                result.errors.push(e);
+                result.value.push_str(token.raw);
+                continue;
+            }
+            None if argument.is_some() => {
+                result.errors.push(format!("'{}' is not a built-in token and does not take an argument", token.raw));
+                result.value.push_str(token.raw);
+                continue;
+            }
+            None => lookup(token.name),
+        };
+
+        match (value, &token.modifier) {
+            (Some(value), Modifier::Default(_) | Modifier::Required(_)) if !value.is_empty() => result.value.push_str(&value),
+            (_, Modifier::Default(fallback)) => expand_into(fallback, context, lookup, result),
+            (_, Modifier::Required(message)) => {
+                let message = if message.is_empty() { "is not set" } else { message };
+                result.errors.push(format!("{}: {}", token.name, message));
+                result.value.push_str(token.raw);
+            }
+            (Some(value), _) => result.value.push_str(&value),
+            (None, _) => {
+                if !result.undefined.iter().any(|n| n == token.name) {
+                    result.undefined.push(token.name.to_string());
+                }
+                result.value.push_str(token.raw);
+            }
+        }
+    }
+}
+
+/// Names reserved for built-in tokens
+pub const BUILTIN_TOKENS: [&str; 7] = ["varo.path", "node.dir", "node.id", "preset.id", "user", "platform", "date"];
+
+/// Value of a built-in token: `None` if `name` is not a built-in, `Some(Ok(None))` if it is unset here
+fn builtin_value<F>(name: &str, argument: Option<&str>, context: &ExpandContext, lookup: &mut F) -> Option<Result<Option<String>, String>>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let value = match name {
+        "varo.path" => context.varo_path.clone().or_else(|| lookup("VARO_PATH")),
+        "node.dir" => context.node_dir.clone(),
+        "node.id" => context.node_id.clone(),
+        "preset.id" => context.preset_id.clone(),
+        "user" => Some(platform::get_os_username()),
+        "platform" => Some(platform::get_platform()),
+        "date" => return Some(format_date(argument.unwrap_or("%Y-%m-%d")).map(Some)),
+        _ => return None,
+    };
+
+    if argument.is_some() {
+        return Some(Err(format!("Built-in token '{}' does not take an argument", name)));
+    }
+    Some(Ok(value))
+}
+
+/// Formats the current local time with a strftime-style format
+fn format_date(format: &str) -> Result<String, String> {
+    let mut output = String::new();
+    write!(output, "{}", chrono::Local::now().format(format))
+        .map_err(|_| format!("Invalid date format: {}", format))?;
+    Ok(output)
+}
+
+/// A problem found while resolving a list of env entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpansionIssue {
+    /// Variables that reference each other, e.g. `["A", "B", "A"]`
+    Cycle(Vec<String>),
+    /// `variable`'s value references `reference`, which is not defined anywhere
+    Undefined { variable: String, reference: String },
+    /// `variable`'s value could not be expanded, e.g. a `${NAME:?message}` token failed
+    Failed { variable: String, message: String },
+}
+
+impl ExpansionIssue {
+    pub fn message(&self) -> String {
+        match self {
+            ExpansionIssue::Cycle(names) => format!(
+                "Env variables reference each other in a cycle: {}",
+                names.join(" -> ")
+            ),
+            ExpansionIssue::Undefined { variable, reference } => format!(
+                "Env variable '{}' references undefined variable '${{{}}}'",
+                variable, reference
+            ),
+            ExpansionIssue::Failed { variable, message } => format!(
+                "Env variable '{}' could not be expanded: {}",
+                variable, message
+            ),
+        }
+    }
+}
+
+/// Applies env entries on top of `env_map`, resolving references between them in dependency order.
+///
+/// - `${OTHER}` refers to the final value of `OTHER` in this list, or to `env_map` if the list does not define it.
+/// - `${SELF}` inside an entry for `SELF` refers to its value before that entry, so `PATH=${PATH}:x` extends
+///   the inherited value.
+/// - Entries for the same name are applied in file order.
+///
+/// References inside a cycle and undefined references are kept as written and returned as issues.
+pub fn resolve_env_layer(env_map: &mut HashMap<String, String>, env_vars: &[EnvVar], context: &ExpandContext) -> Vec<ExpansionIssue> {
+    // Entries grouped by name, in order of first appearance
+    let mut names: Vec<&str> = Vec::new();
+    let mut entries: HashMap<&str, Vec<&EnvVar>> = HashMap::new();
+    for env_var in env_vars {
+        let name = env_var.name.as_str();
+        if !entries.contains_key(name) {
+            names.push(name);
+        }
+        entries.entry(name).or_default().push(env_var);
+    }
+
+    // References to other variables defined in this list
+    let dependencies: HashMap<&str, Vec<&str>> = names.iter()
+        .map(|&name| {
+            let mut deps: Vec<&str> = Vec::new();
+            for env_var in &entries[name] {
+                for reference in references(&env_var.value) {
+                    if reference != name && entries.contains_key(reference) && !deps.contains(&reference) {
+                        deps.push(reference);
+                    }
+                }
+            }
+            (name, deps)
+        })
+        .collect();
+
+    let mut sorter = TopoSort::new(&dependencies);
+    for &name in &names {
+        sorter.visit(name);
+    }
+
+    let mut issues: Vec<ExpansionIssue> = sorter.cycles.iter()
+        .map(|cycle| ExpansionIssue::Cycle(cycle.iter().map(|s| s.to_string()).collect()))
+        .collect();
+
+    for name in sorter.order {
+        let in_cycle = sorter.cyclic.contains(name);
+        for env_var in &entries[name] {
+            let expanded = expand(&env_var.value, context, &mut |reference| {
+                // References between cycle members stay unexpanded
+                if in_cycle && reference != name && sorter.cyclic.contains(reference) {
+                    return None;
+                }
+                env_map.get(reference).cloned()
+            });
+
+            for reference in expanded.undefined {
+                if in_cycle && sorter.cyclic.contains(reference.as_str()) {
+                    continue;
+                }
+                let issue = ExpansionIssue::Undefined { variable: name.to_string(), reference };
+                if !issues.contains(&issue) {
+                    issues.push(issue);
+                }
+            }
+            for message in expanded.errors {
+                issues.push(ExpansionIssue::Failed { variable: name.to_string(), message });
+            }
+
+            let operation = env_var.operation.as_deref().unwrap_or("set");
+            apply_env_operation(env_map, name, &expanded.value, operation);
+        }
+    }
+
+    issues
+}
+
+/// Depth-first topological sort that records cycles instead of failing on them
+struct TopoSort<'a, 'd> {
+    dependencies: &'d HashMap<&'a str, Vec<&'a str>>,
+    done: HashSet<&'a str>,
+    stack: Vec<&'a str>,
+    order: Vec<&'a str>,
+    cycles: Vec<Vec<&'a str>>,
+    cyclic: HashSet<&'a str>,
+}
+
+impl<'a, 'd> TopoSort<'a, 'd> {
+    fn new(dependencies: &'d HashMap<&'a str, Vec<&'a str>>) -> Self {
+        Self {
+            dependencies,
+            done: HashSet::new(),
+            stack: Vec::new(),
+            order: Vec::new(),
+            cycles: Vec::new(),
+            cyclic: HashSet::new(),
+        }
+    }
+
+    fn visit(&mut self, name: &'a str) {
+        if self.done.contains(name) {
+            return;
+        }
+        if let Some(start) = self.stack.iter().position(|&n| n == name) {
+            let mut cycle = self.stack[start..].to_vec();
+            self.cyclic.extend(cycle.iter().copied());
+            cycle.push(name);
+            self.cycles.push(cycle);
+            return;
+        }
+
+        self.stack.push(name);
+        let dependencies = self.dependencies.get(name).cloned().unwrap_or_default();
+        for dependency in dependencies {
+            self.visit(dependency);
+        }
+        self.stack.pop();
+
+        self.done.insert(name);
+        self.order.push(name);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::utils::platform::get_path_separator;
+
+/// Shell or file format an environment can be exported as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// POSIX shell script for bash and zsh (`source varo.sh`)
+    Bash,
+    /// fish script (`source varo.fish`)
+    Fish,
+    /// PowerShell script (`. .\varo.ps1`)
+    PowerShell,
+    /// cmd batch file (`call varo.bat`)
+    Cmd,
+    /// `.env` file with fully resolved values
+    Dotenv,
+}
+
+/// Format names accepted by [`ExportFormat::parse`]
+pub const EXPORT_FORMATS: [&str; 5] = ["bash", "fish", "powershell", "cmd", "dotenv"];
+
+impl ExportFormat {
+    /// Parses a format name. Common aliases such as `zsh`, `pwsh`, `bat` and `env` are accepted.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "bash" | "zsh" | "sh" => Some(ExportFormat::Bash),
+            "fish" => Some(ExportFormat::Fish),
+            "powershell" | "pwsh" | "ps1" => Some(ExportFormat::PowerShell),
+            "cmd" | "bat" => Some(ExportFormat::Cmd),
+            "dotenv" | "env" | ".env" => Some(ExportFormat::Dotenv),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Bash => "bash",
+            ExportFormat::Fish => "fish",
+            ExportFormat::PowerShell => "powershell",
+            ExportFormat::Cmd => "cmd",
+            ExportFormat::Dotenv => "dotenv",
+        }
+    }
+
+    /// File extension conventionally used for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Bash => "sh",
+            ExportFormat::Fish => "fish",
+            ExportFormat::PowerShell => "ps1",
+            ExportFormat::Cmd => "bat",
+            ExportFormat::Dotenv => "env",
+        }
+    }
+
+    fn comment_prefix(&self) -> &'static str {
+        match self {
+            ExportFormat::Cmd => "REM ",
+            _ => "# ",
+        }
+    }
+}
+
+/// A variable to write to an exported environment
+#[derive(Debug, Clone)]
+pub struct ExportVar {
+    pub name: String,
+    /// New value, `None` when the variable is removed
+    pub value: Option<String>,
+    /// Value the variable is appended or prepended to, if any.
+    /// When `value` still contains it, scripts extend the variable of the shell they run in instead
+    /// of replacing it, so e.g. `PATH` entries added after Varo started are kept.
+    pub base: Option<String>,
+}
+
+/// How one variable is written out
+enum Assignment<'a> {
+    Set(&'a str),
+    Unset,
+    /// `before`, the current value and `after`, joined with the path separator.
+    /// The current value and its separator are left out when it is empty.
+    Extend { before: Option<&'a str>, after: Option<&'a str> },
+}
+
+fn assignment<'a>(var: &'a ExportVar, separator: &str) -> Assignment<'a> {
+    let value = match &var.value {
+        Some(value) => value.as_str(),
+        None => return Assignment::Unset,
+    };
+    let base = match var.base.as_deref() {
+        Some(base) if !base.is_empty() && base != value => base,
+        _ => return Assignment::Set(value),
+    };
+
+    if let Some(before) = value.strip_suffix(base).and_then(|rest| rest.strip_suffix(separator)) {
+        return Assignment::Extend { before: Some(before), after: None };
+    }
+    if let Some(after) = value.strip_prefix(base).and_then(|rest| rest.strip_prefix(separator)) {
+        return Assignment::Extend { before: None, after: Some(after) };
+    }
+    let middle = format!("{}{}{}", separator, base, separator);
+    if let Some(index) = value.find(&middle) {
+        return Assignment::Extend {
+            before: Some(&value[..index]),
+            after: Some(&value[index + middle.len()..]),
+        };
+    }
+    Assignment::Set(value)
+}
+
+/// True for names every supported shell can assign without extra quoting
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Renders variables as a script or `.env` file in the given format.
+/// `header` lines are written as comments at the top. Variables whose names cannot be
+/// assigned in every shell are skipped with a comment.
+/// Fails when a value cannot be represented in the format (line breaks in cmd files).
+pub fn render_env_script(vars: &[ExportVar], format: ExportFormat, header: &[String]) -> Result<String, String> {
+    let separator = get_path_separator();
+    let comment = format.comment_prefix();
+    let mut lines: Vec<String> = Vec::new();
+
+    if format == ExportFormat::Cmd {
+        lines.push("@echo off".to_string());
+    }
+    for line in header {
+        lines.push(format!("{}{}", comment, line));
+    }
+
+    for var in vars {
+        if !is_valid_name(&var.name) {
+            lines.push(format!("{}Skipped '{}': not a valid variable name", comment, var.name.replace(['\r', '\n'], " ")));
+            continue;
+        }
+
+        let line = match format {
+            ExportFormat::Bash => render_bash(&var.name, assignment(var, separator), separator),
+            ExportFormat::Fish => render_fish(&var.name, assignment(var, separator), separator),
+            ExportFormat::PowerShell => render_powershell(&var.name, assignment(var, separator), separator),
+            ExportFormat::Cmd => {
+                if var.value.as_deref().is_some_and(|v| v.contains(['\r', '\n'])) {
+                    return Err(format!("Value of '{}' contains a line break, which cmd files cannot represent", var.name));
+                }
+                render_cmd(&var.name, assignment(var, separator), separator)
+            }
+            // `.env` files cannot refer to the current value, so always write the resolved one
+            ExportFormat::Dotenv => match &var.value {
+                Some(value) => format!("{}={}", var.name, quote_dotenv(value)),
+                None => format!("# unset {}", var.name),
+            },
+        };
+        lines.push(line);
+    }
+
+    let newline = if format == ExportFormat::Cmd { "\r\n" } else { "\n" };
+    let mut script = lines.join(newline);
+    script.push_str(newline);
+    Ok(script)
+}
+
+/// Joins the literal parts around the current value, adding separators on the literal side
+fn extend_parts<'a>(before: Option<&'a str>, after: Option<&'a str>, separator: &str) -> (Option<String>, Option<String>) {
+    (
+        before.map(|b| format!("{}{}", b, separator)),
+        after.map(|a| format!("{}{}", separator, a)),
+    )
+}
+
+/// The value without the current one, used when the variable is unset or empty
+fn joined_without_base(before: Option<&str>, after: Option<&str>, separator: &str) -> String {
+    [before, after].into_iter().flatten().collect::<Vec<_>>().join(separator)
+}
+
+fn quote_posix(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn render_bash(name: &str, assignment: Assignment, separator: &str) -> String {
+    match assignment {
+        Assignment::Set(value) => format!("export {}={}", name, quote_posix(value)),
+        Assignment::Unset => format!("unset {}", name),
+        Assignment::Extend { before, after } => {
+            let (before_part, after_part) = extend_parts(before, after, separator);
+            let extended: String = [
+                before_part.map(|b| quote_posix(&b)),
+                Some(format!("\"${}\"", name)),
+                after_part.map(|a| quote_posix(&a)),
+            ].into_iter().flatten().collect();
+            // `${NAME:-}` keeps the test working under `set -u`
+            format!(
+                "if [ -n \"${{{name}:-}}\" ]; then export {name}={extended}; else export {name}={}; fi",
+                quote_posix(&joined_without_base(before, after, separator))
+            )
+        }
+    }
+}
+
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn render_fish(name: &str, assignment: Assignment, separator: &str) -> String {
+    match assignment {
+        Assignment::Set(value) => format!("set -gx {} {}", name, quote_fish(value)),
+        Assignment::Unset => format!("set -e {}", name),
+        Assignment::Extend { before, after } => {
+            let (before_part, after_part) = extend_parts(before, after, separator);
+            let extended: String = [
+                before_part.map(|b| quote_fish(&b)),
+                Some(format!("\"${}\"", name)),
+                after_part.map(|a| quote_fish(&a)),
+            ].into_iter().flatten().collect();
+            format!(
+                "if test -n \"${name}\"; set -gx {name} {extended}; else; set -gx {name} {}; end",
+                quote_fish(&joined_without_base(before, after, separator))
+            )
+        }
+    }
+}
+
+fn quote_powershell(value: &str) -> String {
+    // Single-quoted strings are literal; the typographic quotes PowerShell also accepts are doubled too
+    let mut quoted = String::from("'");
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn render_powershell(name: &str, assignment: Assignment, separator: &str) -> String {
+    match assignment {
+        Assignment::Set(value) => format!("$env:{} = {}", name, quote_powershell(value)),
+        Assignment::Unset => format!("Remove-Item Env:{} -ErrorAction SilentlyContinue", name),
+        Assignment::Extend { before, after } => {
+            let (before_part, after_part) = extend_parts(before, after, separator);
+            let extended = [
+                before_part.map(|b| quote_powershell(&b)),
+                Some(format!("$env:{}", name)),
+                after_part.map(|a| quote_powershell(&a)),
+            ].into_iter().flatten().collect::<Vec<_>>().join(" + ");
+            format!(
+                "$env:{name} = if ($env:{name}) {{ {extended} }} else {{ {} }}",
+                quote_powershell(&joined_without_base(before, after, separator))
+            )
+        }
+    }
+}
+
+/// Escapes text for use inside `set "NAME=..."`; `%` would otherwise start a variable reference
+fn escape_cmd(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+fn render_cmd(name: &str, assignment: Assignment, separator: &str) -> String {
+    match assignment {
+        Assignment::Set(value) => format!("set \"{}={}\"", name, escape_cmd(value)),
+        Assignment::Unset => format!("set \"{}=\"", name),
+        Assignment::Extend { before, after } => {
+            let (before_part, after_part) = extend_parts(before, after, separator);
+            let extended = format!(
+                "{}%{}%{}",
+                escape_cmd(before_part.as_deref().unwrap_or("")),
+                name,
+                escape_cmd(after_part.as_deref().unwrap_or(""))
+            );
+            format!(
+                "if defined {name} (set \"{name}={extended}\") else (set \"{name}={}\")",
+                escape_cmd(&joined_without_base(before, after, separator))
+            )
+        }
+    }
+}
+
+/// Single quotes keep values literal; values containing a quote or line break use escaped double quotes
+fn quote_dotenv(value: &str) -> String {
+    if !value.contains(['\'', '\n', '\r']) {
+        return format!("'{}'", value);
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str(r"\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str(r"\n"),
+            '\r' => quoted.push_str(r"\r"),
+            other => quoted.push(other),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size and modification time of a file, used to tell whether it changed since it was last read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    /// Stamp of the file at `path`, or `None` if it cannot be read
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        Some(Self { len: meta.len(), modified: meta.modified().ok() })
+    }
+
+    /// Modification time in milliseconds since the Unix epoch, 0 when unknown
+    pub fn modified_millis(&self) -> u64 {
+        self.modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// Write `contents` to `path` so readers only ever see the old or the new file.
+/// The data goes to a temporary file next to `path` first, which is flushed to disk and then
+/// renamed over the target. The temporary file is removed again if any step fails.
+pub fn write_file_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let file_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Invalid file path: {}", path.display()))?;
+    // A leading dot and a `.tmp` extension keep scanners that look for `.json`/`.env` files away from it
+    let temp_path = dir.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp_path, path)
+    })();
+
+    result.map_err(|e| {
+        let _ = fs::remove_file(&temp_path);
+        format!("Failed to write '{}': {}", path.display(), e)
+    })
+}
\ No newline at end of file
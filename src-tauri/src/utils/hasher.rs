@@ -1,15 +1,25 @@
 use blake3;
-use std::path::{Path};
+use std::path::{Component, Path};
 
 pub struct Hasher;
 
 impl Hasher {
-    /// Normalize the path by extracting the filename and lowercasing it
-    pub fn normalize(path: &Path) -> String {
-        path.file_name()
-            .and_then(|s| s.to_str())
-            .map(|s| s.to_lowercase())
-            .unwrap_or_default()
+    /// Normalize the path to its lowercased location relative to `root`, with `/` separators.
+    /// Paths outside `root` are normalized from their absolute location instead.
+    pub fn normalize(path: &Path, root: &Path) -> String {
+        let relative = match path.strip_prefix(root) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+        };
+        relative.components()
+            .filter_map(|c| match c {
+                Component::Prefix(prefix) => Some(prefix.as_os_str().to_string_lossy().to_lowercase()),
+                Component::Normal(part) => Some(part.to_string_lossy().to_lowercase()),
+                Component::ParentDir => Some("..".to_string()),
+                Component::RootDir | Component::CurDir => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/")
     }
 
     /// Generate a stable ID from any input string
@@ -19,9 +29,25 @@ impl Hasher {
         hex_string[..12.min(hex_string.len())].to_string()
     }
 
-    /// Generate an ID directly from a Path (shortcut method)
-    pub fn generate_id_from_path(path: &Path) -> String {
-        let normalized = Self::normalize(path);
+    /// Full hex hash of several inputs. Each part is length-prefixed, so `["ab", "c"]` and `["a", "bc"]` differ.
+    pub fn hash_parts(parts: &[&str]) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for part in parts {
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Full hex hash of a file's contents, used to tell whether a file with a new timestamp really changed
+    pub fn hash_content(content: &[u8]) -> String {
+        blake3::hash(content).to_hex().to_string()
+    }
+
+    /// Generate an ID from a file's path relative to `root`, so it stays the same when `root` is
+    /// moved or reached through another mount. Files outside `root` use their absolute path.
+    pub fn generate_id_from_path(path: &Path, root: &Path) -> String {
+        let normalized = Self::normalize(path, root);
         Self::generate_id_from_str(&normalized)
     }
 }
\ No newline at end of file
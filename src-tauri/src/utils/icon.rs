@@ -1,95 +1,109 @@
-use std::path::PathBuf;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
 use std::fs;
-use base64::engine::general_purpose;
-use base64::Engine;
-use crate::utils::env::expand_env_vars;
+use image::imageops::FilterType;
+use image::ImageFormat;
+use crate::utils::env::expand_env_vars_with_context;
+use crate::utils::expand::ExpandContext;
+use crate::utils::hasher::Hasher;
+
+const SVG_MIME_TYPE: &str = "image/svg+xml";
 
 /// Resolves the full path to an icon file by expanding environment variables
-/// and prepending VARO_PATH if the input is a relative path.
-/// Prints errors to stderr if anything goes wrong.
-pub fn resolve_icon_file_path(raw_icon_path: &str) -> Option<PathBuf> {
+/// and prepending `VARO_PATH/icons` if the input is a relative path.
+/// Built-in tokens such as `${node.dir}` and the VARO_PATH itself are taken from `context`;
+/// the `VARO_PATH` variable is only used when the context has none.
+/// Returns a description of the problem if the icon cannot be found.
+pub fn resolve_icon_file_path(raw_icon_path: &str, context: &ExpandContext) -> Result<PathBuf, String> {
     if raw_icon_path.trim().is_empty() {
-        eprintln!("Icon path is empty — skipping.");
-        return None;
+        return Err("Icon path is empty".to_string());
     }
 
-    let expanded_path = expand_env_vars(raw_icon_path);
-
-    let varo_root = match std::env::var("VARO_PATH") {
-        Ok(path) => PathBuf::from(path),
-        Err(_) => {
-            eprintln!("Environment variable 'VARO_PATH' is not set — cannot resolve icon path.");
-            return None;
-        }
-    };
+    let expanded_path = expand_env_vars_with_context(raw_icon_path, context);
 
     let full_icon_path = if PathBuf::from(&expanded_path).is_absolute() {
         PathBuf::from(&expanded_path)
     } else {
+        let varo_root = context.varo_path.clone()
+            .or_else(|| std::env::var("VARO_PATH").ok())
+            .map(PathBuf::from)
+            .ok_or_else(|| "VARO_PATH is not set, cannot resolve relative icon path".to_string())?;
         varo_root.join("icons").join(&expanded_path)
     };
 
     if !full_icon_path.exists() {
-        eprintln!("Icon file does not exist at path: {}", full_icon_path.display());
-        return None;
+        return Err(format!("Icon file does not exist at path: {}", full_icon_path.display()));
     }
 
-    Some(full_icon_path)
+    Ok(full_icon_path)
+}
+
+/// The content of an icon file, keyed by the blake3 hash of that content so that every node
+/// showing the same icon shares one copy
+#[derive(Debug)]
+pub struct Icon {
+    pub key: String,
+    pub mime: String,
+    pub bytes: Vec<u8>,
+}
+
+impl Icon {
+    pub fn new(mime: impl Into<String>, bytes: Vec<u8>) -> Self {
+        Self { key: Hasher::hash_content(&bytes), mime: mime.into(), bytes }
+    }
 }
 
-/// Loads the contents of an icon file and converts it into an inline data URI.
-/// Handles SVG files specially by sanitizing them and creating proper data URIs.
-/// Returns an empty string on failure.
-pub fn load_icon_data_uri(icon_path: &PathBuf) -> String {
+/// Loads the contents of an icon file.
+/// Handles SVG files specially by sanitizing them.
+pub fn load_icon(icon_path: &Path) -> Result<Icon, String> {
     match icon_path.extension().and_then(|ext| ext.to_str()) {
-        Some("svg") => load_svg_as_data_uri(icon_path),
-        Some(ext) => load_image_as_data_uri(icon_path, ext),
-        None => {
-            eprintln!("Icon file has no extension: {}", icon_path.display());
-            String::new()
-        }
+        Some("svg") => load_svg(icon_path),
+        Some(ext) => load_image(icon_path, ext),
+        None => Err(format!("Icon file has no extension: {}", icon_path.display())),
     }
 }
 
-/// Loads an SVG file and converts it to a proper data URI with sanitization
-fn load_svg_as_data_uri(icon_path: &PathBuf) -> String {
-    match fs::read_to_string(icon_path) {
-        Ok(svg_content) => {
-            let sanitized_svg = sanitize_svg_content(&svg_content);
-            if sanitized_svg.is_empty() {
-                eprintln!("SVG file is empty or invalid: {}", icon_path.display());
-                return String::new();
-            }
-            
-            // Create proper data URI for SVG
-            format!("data:image/svg+xml;base64,{}", 
-                general_purpose::STANDARD.encode(sanitized_svg.as_bytes()))
-        }
-        Err(e) => {
-            eprintln!("Failed to read SVG file {}: {}", icon_path.display(), e);
-            String::new()
-        }
+/// Loads an SVG file with sanitization
+fn load_svg(icon_path: &Path) -> Result<Icon, String> {
+    let svg_content = fs::read_to_string(icon_path)
+        .map_err(|e| format!("Failed to read SVG file {}: {}", icon_path.display(), e))?;
+
+    let sanitized_svg = sanitize_svg_content(&svg_content);
+    if sanitized_svg.is_empty() {
+        return Err(format!("SVG file is empty or invalid: {}", icon_path.display()));
     }
+    
+    Ok(Icon::new(SVG_MIME_TYPE, sanitized_svg.into_bytes()))
 }
 
-/// Loads a raster image file and converts it to a data URI
-fn load_image_as_data_uri(icon_path: &PathBuf, extension: &str) -> String {
+/// Loads a raster image file
+fn load_image(icon_path: &Path, extension: &str) -> Result<Icon, String> {
     let mime_type = get_mime_type_for_extension(extension);
     
-    match fs::read(icon_path) {
-        Ok(bytes) => {
-            if bytes.is_empty() {
-                eprintln!("Image file is empty: {}", icon_path.display());
-                return String::new();
-            }
-            
-            format!("data:{};base64,{}", mime_type, general_purpose::STANDARD.encode(bytes))
-        }
-        Err(e) => {
-            eprintln!("Failed to read image file {}: {}", icon_path.display(), e);
-            String::new()
-        }
+    let bytes = fs::read(icon_path)
+        .map_err(|e| format!("Failed to read image file {}: {}", icon_path.display(), e))?;
+    if bytes.is_empty() {
+        return Err(format!("Image file is empty: {}", icon_path.display()));
+    }
+    
+    Ok(Icon::new(mime_type, bytes))
+}
+
+/// A copy of a raster icon scaled down to fit `size`×`size` pixels, encoded as PNG. Returns `None`
+/// for SVG icons, which scale on their own, and for icons that already fit or cannot be decoded.
+pub fn resize_icon(icon: &Icon, size: u32) -> Option<Icon> {
+    if icon.mime == SVG_MIME_TYPE {
+        return None;
     }
+    let image = image::load_from_memory(&icon.bytes).ok()?;
+    if image.width() <= size && image.height() <= size {
+        return None;
+    }
+
+    let resized = image.resize(size, size, FilterType::Lanczos3);
+    let mut bytes = Vec::new();
+    resized.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).ok()?;
+    Some(Icon::new("image/png", bytes))
 }
 
 /// Sanitizes SVG content by extracting just the SVG element and its contents
@@ -112,8 +126,7 @@ fn sanitize_svg_content(svg_content: &str) -> String {
         }
     }
     
-    // If no valid <svg> tag found, return original but warn
-    eprintln!("Warning: No valid <svg> element found in SVG file");
+    // If no valid <svg> tag found, return original content as-is
     trimmed.to_string()
 }
 
@@ -127,7 +140,7 @@ fn get_mime_type_for_extension(extension: &str) -> &'static str {
         "bmp" => "image/bmp",
         "ico" => "image/x-icon",
         "tiff" | "tif" => "image/tiff",
-        "svg" => "image/svg+xml",
+        "svg" => SVG_MIME_TYPE,
         "avif" => "image/avif",
         "heic" | "heif" => "image/heic",
         _ => "image/png", // Default fallback
@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Where `varo_log!` debug output is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogMode {
+    Stdout = 0,
+    Stderr = 1,
+    Off = 2,
+}
+
+static LOG_MODE: AtomicU8 = AtomicU8::new(LogMode::Stdout as u8);
+
+/// Set the destination for debug output. The app logs to stdout; the CLI keeps stdout
+/// clean for command results and sends debug output to stderr or disables it.
+pub fn set_log_mode(mode: LogMode) {
+    LOG_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn get_log_mode() -> LogMode {
+    match LOG_MODE.load(Ordering::Relaxed) {
+        0 => LogMode::Stdout,
+        1 => LogMode::Stderr,
+        _ => LogMode::Off,
+    }
+}
+
+/// Debug output macro with the same syntax as `println!`, honoring the current `LogMode`
+macro_rules! varo_log {
+    ($($arg:tt)*) => {
+        match $crate::utils::log::get_log_mode() {
+            $crate::utils::log::LogMode::Stdout => println!($($arg)*),
+            $crate::utils::log::LogMode::Stderr => eprintln!($($arg)*),
+            $crate::utils::log::LogMode::Off => {}
+        }
+    };
+}
+
+pub(crate) use varo_log;
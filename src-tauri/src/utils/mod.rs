@@ -1,7 +1,15 @@
+pub mod log;
+pub mod args;
 pub mod hasher;
 pub mod env;
+pub mod expand;
 pub mod icon;
 pub mod commands;
 pub mod config;
 pub mod platform;
+pub mod program;
+pub mod export;
+pub mod dotenv;
+pub mod files;
+pub mod duplicates;
 pub mod node;
\ No newline at end of file
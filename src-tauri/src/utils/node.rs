@@ -2,55 +2,125 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde_json::Value;
-use crate::models::entities::{VaroNode, Status, Access, Command, EnvVar, EnvPreset};
+use crate::models::entities::{VaroNode, Status, Access, Command};
+use crate::models::diagnostics::{Diagnostic, Severity, locate_json_key};
+use crate::utils::args::split_command_line;
+use crate::utils::expand::ExpandContext;
 use crate::utils::hasher::Hasher;
-use crate::utils::icon::{resolve_icon_file_path, load_icon_data_uri};
-use crate::utils::env::parse_env_vars_from_json;
+use crate::utils::program::PathType;
+use crate::utils::icon::resolve_icon_file_path;
+use crate::utils::env::{parse_env_vars_from_json, validate_env_vars};
+use crate::utils::log::varo_log;
 
 
-fn parse_commands_from_json(json: &Value, path: &PathBuf) -> Vec<Command> {
+/// Build a diagnostic for a specific field, pointing at the field's position in the file when found
+fn field_diagnostic(severity: Severity, path: &Path, content: &str, field: &str, message: String) -> Diagnostic {
+    Diagnostic::new(severity, path.to_str(), message)
+        .with_field(field)
+        .with_location(locate_json_key(content, field))
+}
+
+/// Parses the `commands` array. Returns `None` when any command is invalid, since a node that
+/// runs only some of its commands would not do what it says.
+fn parse_commands_from_json(json: &Value, path: &PathBuf, content: &str, diagnostics: &mut Vec<Diagnostic>) -> Option<Vec<Command>> {
     match json.get("commands") {
         Some(commands_val) => {
             if let Some(commands_arr) = commands_val.as_array() {
-                commands_arr.iter().filter_map(|item| {
-                    item.as_object().map(|cmd| Command {
-                        path: cmd.get("path").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                        path_type: cmd.get("path_type").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                        args: cmd.get("args").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                        wait: cmd.get("wait").and_then(|v| v.as_bool()).unwrap_or(true),
-                    })
-                }).collect()
+                let mut commands = Vec::new();
+                let mut invalid = 0;
+                for (index, item) in commands_arr.iter().enumerate() {
+                    match parse_command_from_json(item, path, content, diagnostics) {
+                        Ok(command) => commands.push(command),
+                        Err((field, e)) => {
+                            diagnostics.push(field_diagnostic(Severity::Error, path, content, field,
+                                format!("Command {}: {}", index + 1, e)));
+                            invalid += 1;
+                        }
+                    }
+                }
+                if invalid > 0 {
+                    diagnostics.push(field_diagnostic(Severity::Error, path, content, "commands",
+                        format!("Node skipped because {} of its {} commands could not be parsed", invalid, commands_arr.len())));
+                    return None;
+                }
+                Some(commands)
             } else {
-                eprintln!("Error: 'commands' field must be an array in '{}'", path.display());
-                vec![]
+                diagnostics.push(field_diagnostic(Severity::Error, path, content, "commands",
+                    "'commands' field must be an array".to_string()));
+                None
             }
         }
         None => {
-            eprintln!("Error: Missing 'commands' field in '{}'", path.display());
-            vec![]
+            diagnostics.push(Diagnostic::error(path.to_str(), "Missing 'commands' field").with_field("commands"));
+            None
         }
     }
 }
 
-fn parse_status_from_json(json: &Value, path: &PathBuf) -> Option<Status> {
-    match json.get("status").and_then(|v| v.as_object()) {
-        Some(s) => Some(Status {
+/// Parses one entry of `commands`, returning the field at fault when it is invalid
+fn parse_command_from_json(item: &Value, path: &PathBuf, content: &str, diagnostics: &mut Vec<Diagnostic>) -> Result<Command, (&'static str, String)> {
+    let cmd = item.as_object()
+        .ok_or_else(|| ("commands", format!("entries must be objects, found: {}", item)))?;
+    let args = parse_args_from_json(cmd.get("args")).map_err(|e| ("args", e))?;
+    let cwd = parse_cwd_from_json(cmd.get("cwd")).map_err(|e| ("cwd", e))?;
+    let path_type = cmd.get("path_type").and_then(|v| v.as_str()).unwrap_or("");
+    if !path_type.is_empty() && PathType::parse(path_type).is_none() {
+        diagnostics.push(field_diagnostic(Severity::Warning, path, content, "path_type",
+            format!("Unknown path_type '{}', expected 'rel', 'abs' or 'url'. The type will be inferred from the path", path_type)));
+    }
+    Ok(Command {
+        path: cmd.get("path").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        path_type: path_type.to_string(),
+        args,
+        cwd,
+        wait: cmd.get("wait").and_then(|v| v.as_bool()).unwrap_or(true),
+    })
+}
+
+/// Parses command `args` given either as a string (split with shell-style quoting)
+/// or as an array of strings (used verbatim, one argument per element)
+fn parse_args_from_json(args: Option<&Value>) -> Result<Vec<String>, String> {
+    match args {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::String(s)) => split_command_line(s),
+        Some(Value::Array(arr)) => arr.iter()
+            .map(|v| v.as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| format!("'args' array entries must be strings, found: {}", v)))
+            .collect(),
+        Some(other) => Err(format!("'args' must be a string or an array of strings, found: {}", other)),
+    }
+}
+
+/// Parses an optional `cwd` string. Empty strings count as unset.
+fn parse_cwd_from_json(cwd: Option<&Value>) -> Result<Option<String>, String> {
+    match cwd {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) if s.trim().is_empty() => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.to_string())),
+        Some(other) => Err(format!("'cwd' must be a string, found: {}", other)),
+    }
+}
+
+fn parse_status_from_json(json: &Value, path: &PathBuf, content: &str, diagnostics: &mut Vec<Diagnostic>) -> Option<Status> {
+    match json.get("status") {
+        None | Some(Value::Null) => None,
+        Some(Value::Object(s)) => Some(Status {
             name: s.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
             color: s.get("color").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         }),
-        None => {
-            eprintln!(
-                "Warning: Missing or invalid 'status' block in file '{}', skipping status",
-                path.display()
-            );
+        Some(_) => {
+            diagnostics.push(field_diagnostic(Severity::Warning, path, content, "status",
+                "'status' must be an object, skipping status".to_string()));
             None
         }
     }
 }
 
-fn parse_access_from_json(json: &Value, path: &PathBuf) -> Option<Access> {
-    match json.get("access").and_then(|v| v.as_object()) {
-        Some(acc) => Some(Access {
+fn parse_access_from_json(json: &Value, path: &PathBuf, content: &str, diagnostics: &mut Vec<Diagnostic>) -> Option<Access> {
+    match json.get("access") {
+        None | Some(Value::Null) => None,
+        Some(Value::Object(acc)) => Some(Access {
             platforms: acc.get("platforms")
                 .and_then(|v| v.as_array())
                 .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
@@ -66,35 +136,35 @@ fn parse_access_from_json(json: &Value, path: &PathBuf) -> Option<Access> {
                 .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
                 .unwrap_or_default(),
         }),
-        None => {
-            eprintln!(
-                "Warning: Missing or invalid 'access' block in file '{}', skipping access",
-                path.display()
-            );
+        Some(_) => {
+            diagnostics.push(field_diagnostic(Severity::Warning, path, content, "access",
+                "'access' must be an object, skipping access".to_string()));
             None
         }
     }
 }
 
-pub fn load_node_from_file(path: &PathBuf) -> Result<VaroNode, String> {
-    let content = match fs::read_to_string(path) {
-        Ok(c) => c,
+/// Parses a single node from the contents of the JSON file at `path`.
+/// `varo_path` is the VARO_PATH the file was found in, used for relative icons and `${varo.path}`.
+/// Without an `id`, the ID is generated from the file's path relative to `varo_path`. The icon file is passed to `load_icon`, which returns the key the icon is served under.
+/// Problems are recorded in `diagnostics`; returns `None` if the node cannot be used at all.
+pub fn parse_node_json(content: &str, path: &PathBuf, varo_path: &Path, load_icon: &dyn Fn(&Path) -> Result<String, String>, diagnostics: &mut Vec<Diagnostic>) -> Option<VaroNode> {
+    let file = path.to_str();
+
+    let json: Value = match serde_json::from_str(content) {
+        Ok(json) => json,
         Err(e) => {
-            eprintln!("Failed to read file '{}': {}", path.display(), e);
-            return Err(format!("Read error: {}", e));
+            diagnostics.push(Diagnostic::from_json_error(file, &e));
+            return None;
         }
     };
 
-    let json: Value = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse JSON in file {}: {}", path.display(), e))?;
-
     let name = match json.get("name").and_then(|v| v.as_str()) {
         Some(n) => n.to_string(),
         None => {
-            eprintln!(
-                "Warning: Missing or invalid 'name' field in file '{}', using 'Untitled'",
-                path.display()
-            );
+            diagnostics.push(Diagnostic::warning(file, "Missing or invalid 'name' field, using 'Untitled'")
+                .with_field("name")
+                .with_location(locate_json_key(content, "name")));
             "Untitled".to_string()
         }
     };
@@ -108,103 +178,125 @@ pub fn load_node_from_file(path: &PathBuf) -> Result<VaroNode, String> {
     let id = json.get("id")
         .and_then(|v| v.as_str())
         .map(|s| s.to_string())
-        .unwrap_or_else(|| Hasher::generate_id_from_path(path));
+        .unwrap_or_else(|| Hasher::generate_id_from_path(path, varo_path));
 
     let category = match json.get("category").and_then(|v| v.as_str()) {
         Some(cat) => cat.to_string(),
         None => {
-            eprintln!(
-                "Warning: Missing or invalid 'category' field in file '{}', using 'Uncategorized'",
-                path.display()
-            );
+            diagnostics.push(Diagnostic::warning(file, "Missing or invalid 'category' field, using 'Uncategorized'")
+                .with_field("category")
+                .with_location(locate_json_key(content, "category")));
             "Uncategorized".to_string()
         }
     };
 
-    let group_id = match json.get("group_id").and_then(|v| v.as_str()) {
-        Some(grp) => grp.to_string(),
-        None => {
-            eprintln!(
-                "Warning: Missing or invalid 'group_id' field in file '{}', using '{}'",
-                path.display(), name
-            );
-            name.to_string()
-        }
-    };
+    // group_id is optional and defaults to the node name
+    let group_id = json.get("group_id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| name.clone());
 
     let default_for_group = json
         .get("default_for_group")
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
     
-    let status = parse_status_from_json(&json, path);
-    let access = parse_access_from_json(&json, path);
+    let cwd = match parse_cwd_from_json(json.get("cwd")) {
+        Ok(cwd) => cwd,
+        Err(e) => {
+            diagnostics.push(field_diagnostic(Severity::Warning, path, content, "cwd",
+                format!("{}, ignoring node cwd", e)));
+            None
+        }
+    };
+
+    let status = parse_status_from_json(&json, path, content, diagnostics);
+    let access = parse_access_from_json(&json, path, content, diagnostics);
 
     let icon_path = json.get("icon").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    let icon_file_path = resolve_icon_file_path(&icon_path);
-    let icon_data = icon_file_path
-        .as_ref()
-        .map(|p| load_icon_data_uri(p))
-        .unwrap_or_default();
+    let icon_context = ExpandContext {
+        varo_path: Some(varo_path.to_string_lossy().to_string()),
+        node_dir: path.parent().map(|dir| dir.to_string_lossy().to_string()),
+        node_id: Some(id.clone()),
+        ..Default::default()
+    };
+    let icon_file = resolve_icon_file_path(&icon_path, &icon_context);
+    let icon_key = match icon_file.as_ref().map_err(|e| e.clone()).and_then(|icon_file| load_icon(icon_file)) {
+        Ok(key) => key,
+        Err(e) => {
+            diagnostics.push(Diagnostic::warning(file, e)
+                .with_field("icon")
+                .with_location(locate_json_key(content, "icon")));
+            String::new()
+        }
+    };
 
     let modified = fs::metadata(path)
         .and_then(|meta| meta.modified())
         .unwrap_or(SystemTime::now());
     let date_modified = modified.duration_since(UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
-        .as_millis() as u64;
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
 
     let env = json.get("env")
         .map(parse_env_vars_from_json)
         .unwrap_or_default();
+    validate_env_vars(&env, path, diagnostics);
 
-    let icon_preview = if icon_data.len() > 50 {
-        format!("{}...", &icon_data[..50])
-    } else {
-        icon_data.clone()
-    };
-
-    let commands = parse_commands_from_json(&json, path);
+    let commands = parse_commands_from_json(&json, path, content, diagnostics)?;
     if commands.is_empty() {
-        return Err(format!("No valid commands found in '{}'", path.display()));
+        diagnostics.push(field_diagnostic(Severity::Error, path, content, "commands",
+            "No valid commands found".to_string()));
+        return None;
     }
 
-    println!("Name: {}", name);
-    println!("Description: {:?}", description);
-    println!("Filepath: {:?}", filepath);
-    println!("Id: {:?}", id);
-    println!("Category: {:?}", category);
-    println!("Group Id: {:?}", group_id);
-    println!("Date Modified: {:?}", date_modified);
-    println!("Default For Group: {:?}", default_for_group);
-    println!("Status: {:?}", status);
-    println!("Access: {:?}", access);
-    println!("Env: {:?}", env);
-    println!("Icon: {:?}", icon_preview);
-    println!("Commands: {:?}", commands);
-    println!("---");
+    varo_log!("Name: {}", name);
+    varo_log!("Description: {:?}", description);
+    varo_log!("Filepath: {:?}", filepath);
+    varo_log!("Id: {:?}", id);
+    varo_log!("Category: {:?}", category);
+    varo_log!("Group Id: {:?}", group_id);
+    varo_log!("Date Modified: {:?}", date_modified);
+    varo_log!("Default For Group: {:?}", default_for_group);
+    varo_log!("Status: {:?}", status);
+    varo_log!("Access: {:?}", access);
+    varo_log!("Env: {:?}", env);
+    varo_log!("Icon: {:?}", icon_key);
+    varo_log!("Cwd: {:?}", cwd);
+    varo_log!("Commands: {:?}", commands);
+    varo_log!("---");
 
     let node = VaroNode {
         access,
         category,
         commands,
+        cwd,
         date_modified,
         default_for_group,
         description,
         env,
         filepath,
         group_id,
-        icon: icon_data,
+        icon: icon_key,
+        icon_file: icon_file.ok().map(|p| p.to_string_lossy().to_string()),
         id,
         name,
         status,
     };
 
-    Ok(node)
+    Some(node)
 }
 
-pub fn load_nodes_in_dir(dir_path: &str) -> Result<Vec<VaroNode>, String> {
-    let mut nodes = Vec::new();
+/// True when `path` has the `.json` extension node files use.
+/// Only the name is checked, so this also works for files that were just removed.
+pub fn is_node_file_name(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Node files in a directory, sorted by path so duplicate ids resolve the same way on every scan
+pub fn list_node_files(dir_path: &str) -> Result<Vec<PathBuf>, String> {
     let dir = Path::new(dir_path);
 
     if !dir.exists() || !dir.is_dir() {
@@ -213,20 +305,10 @@ pub fn load_nodes_in_dir(dir_path: &str) -> Result<Vec<VaroNode>, String> {
 
     let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read directory '{}': {}", dir_path, e))?;
 
-    for entry in entries.flatten() {
-        let path = entry.path();
-        
-        if path.is_file() {
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                if ext.eq_ignore_ascii_case("json") {
-                    if let Ok(node) = load_node_from_file(&path) {
-                        nodes.push(node);
-                    }
-                }
-            }
-        }
-    }
-
-    println!("Fetched {} nodes", nodes.len());
-    Ok(nodes)
+    let mut paths: Vec<PathBuf> = entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_node_file_name(path))
+        .collect();
+    paths.sort();
+    Ok(paths)
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use std::env;
 use std::path::Path;
+use crate::utils::log::varo_log;
 
 /// Returns os username or Guest
 pub fn get_os_username() -> String {
@@ -14,6 +15,38 @@ pub fn get_os_username() -> String {
     "Guest".to_string()
 }
 
+/// Returns the names of the OS groups the current user belongs to, or an empty list
+pub fn get_os_user_groups() -> Vec<String> {
+    #[cfg(target_os = "windows")]
+    {
+        // CSV rows look like "DOMAIN\Group Name","Well-known group",...
+        let output = std::process::Command::new("whoami")
+            .args(["/groups", "/fo", "csv", "/nh"])
+            .output();
+        return match output {
+            Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
+                .lines()
+                .filter_map(|line| line.split("\",\"").next())
+                .map(|name| name.trim_matches('"'))
+                .map(|name| name.rsplit('\\').next().unwrap_or(name).to_string())
+                .filter(|name| !name.is_empty())
+                .collect(),
+            _ => Vec::new(),
+        };
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        match std::process::Command::new("id").arg("-Gn").output() {
+            Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
+                .split_whitespace()
+                .map(|s| s.to_string())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
 /// Returns os name (win, mac, linux, or unknown)
 pub fn get_platform() -> String {
     if cfg!(target_os = "windows") {
@@ -27,16 +60,60 @@ pub fn get_platform() -> String {
     }
 }
 
+/// Returns the separator used between entries of path-list variables such as PATH
+pub fn get_path_separator() -> &'static str {
+    if cfg!(target_os = "windows") {
+        ";"
+    } else {
+        ":"
+    }
+}
+
+/// File system types of network mounts, whose changes native file watchers do not see
+#[cfg(target_os = "linux")]
+const NETWORK_FILE_SYSTEMS: [&str; 9] = ["nfs", "nfs4", "cifs", "smb3", "smbfs", "afs", "9p", "fuse.sshfs", "fuse.rclone"];
+
+/// Returns true when `path` is on a network share, where native change notifications are unreliable.
+/// UNC paths (`\\server\share`, `//server/share`) count everywhere; on Linux the mount table is
+/// checked for NFS, SMB and similar mounts.
+pub fn is_network_path(path: &Path) -> bool {
+    let text = path.to_string_lossy();
+    if text.starts_with("\\\\") || text.starts_with("//") {
+        return true;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let mounts = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+        // The most specific mount point containing the path decides
+        let file_system = mounts.lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let mount_point = fields.nth(1)?.replace("\\040", " ");
+                let file_system = fields.next()?;
+                path.starts_with(&mount_point).then(|| (mount_point.len(), file_system))
+            })
+            .max_by_key(|(length, _)| *length)
+            .map(|(_, file_system)| file_system);
+        if let Some(file_system) = file_system {
+            return NETWORK_FILE_SYSTEMS.contains(&file_system);
+        }
+    }
+
+    false
+}
+
 /// Opens a directory in the system's default file manager
 pub fn open_directory_in_folder(dir: &Path) -> bool {
     if !dir.exists() {
-        println!("[Platform] Directory does not exist: {}", dir.display());
+        varo_log!("[Platform] Directory does not exist: {}", dir.display());
         return false;
     }
 
-    println!("[Platform] Attempting to open directory: {}", dir.display());
-    println!("[Platform] Directory exists: {}", dir.exists());
-    println!("[Platform] Is directory: {}", dir.is_dir());
+    varo_log!("[Platform] Attempting to open directory: {}", dir.display());
+    varo_log!("[Platform] Directory exists: {}", dir.exists());
+    varo_log!("[Platform] Is directory: {}", dir.is_dir());
 
     #[cfg(target_os = "windows")]
     {
@@ -44,8 +121,8 @@ pub fn open_directory_in_folder(dir: &Path) -> bool {
         let path_str = dir.to_string_lossy();
         let windows_path = path_str.replace('/', "\\");
         
-        println!("[Platform] Original path: {}", path_str);
-        println!("[Platform] Windows path: {}", windows_path);
+        varo_log!("[Platform] Original path: {}", path_str);
+        varo_log!("[Platform] Windows path: {}", windows_path);
         
         // Try different methods for Windows
         let mut result = std::process::Command::new("explorer")
@@ -54,7 +131,7 @@ pub fn open_directory_in_folder(dir: &Path) -> bool {
             
         // If the first method fails, try with /e flag (opens in folder view)
         if result.is_err() {
-            println!("[Platform] First attempt failed, trying with /e flag");
+            varo_log!("[Platform] First attempt failed, trying with /e flag");
             result = std::process::Command::new("explorer")
                 .arg("/e")
                 .arg(&windows_path)
@@ -63,11 +140,11 @@ pub fn open_directory_in_folder(dir: &Path) -> bool {
             
         match result {
             Ok(_) => {
-                println!("[Platform] Successfully launched explorer");
+                varo_log!("[Platform] Successfully launched explorer");
                 return true;
             },
             Err(e) => {
-                println!("[Platform] Failed to launch explorer: {}", e);
+                varo_log!("[Platform] Failed to launch explorer: {}", e);
                 return false;
             }
         }
@@ -75,16 +152,16 @@ pub fn open_directory_in_folder(dir: &Path) -> bool {
 
     #[cfg(target_os = "macos")]
     {
-        println!("[Platform] Opening with macOS 'open' command");
+        varo_log!("[Platform] Opening with macOS 'open' command");
         match std::process::Command::new("open")
             .arg(dir)
             .spawn() {
             Ok(_) => {
-                println!("[Platform] Successfully opened finder");
+                varo_log!("[Platform] Successfully opened finder");
                 return true;
             },
             Err(e) => {
-                println!("[Platform] Failed to open finder: {}", e);
+                varo_log!("[Platform] Failed to open finder: {}", e);
                 return false;
             }
         }
@@ -95,25 +172,25 @@ pub fn open_directory_in_folder(dir: &Path) -> bool {
         // Try common Linux file managers
         let file_managers = ["xdg-open", "nautilus", "dolphin", "thunar", "pcmanfm"];
         
-        println!("[Platform] Trying Linux file managers: {:?}", file_managers);
+        varo_log!("[Platform] Trying Linux file managers: {:?}", file_managers);
         
         for manager in &file_managers {
-            println!("[Platform] Trying file manager: {}", manager);
+            varo_log!("[Platform] Trying file manager: {}", manager);
             if let Ok(_) = std::process::Command::new(manager)
                 .arg(dir)
                 .spawn() {
-                println!("[Platform] Successfully opened with: {}", manager);
+                varo_log!("[Platform] Successfully opened with: {}", manager);
                 return true;
             }
         }
         
-        println!("[Platform] No suitable file manager found");
+        varo_log!("[Platform] No suitable file manager found");
         return false;
     }
 
     #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
     {
-        println!("[Platform] Unsupported platform: {}", env::consts::OS);
+        varo_log!("[Platform] Unsupported platform: {}", env::consts::OS);
         return false;
     }
 }
@@ -121,11 +198,11 @@ pub fn open_directory_in_folder(dir: &Path) -> bool {
 /// Opens a file in the system's default file manager and selects/highlights it
 pub fn open_file_in_folder(file_path: &Path) -> bool {
     if !file_path.exists() {
-        println!("[Platform] File does not exist: {}", file_path.display());
+        varo_log!("[Platform] File does not exist: {}", file_path.display());
         return false;
     }
 
-    println!("[Platform] Attempting to select file in folder: {}", file_path.display());
+    varo_log!("[Platform] Attempting to select file in folder: {}", file_path.display());
 
     #[cfg(target_os = "windows")]
     {
@@ -133,18 +210,18 @@ pub fn open_file_in_folder(file_path: &Path) -> bool {
         let path_str = file_path.to_string_lossy();
         let windows_path = path_str.replace('/', "\\");
         
-        println!("[Platform] Windows file path: {}", windows_path);
+        varo_log!("[Platform] Windows file path: {}", windows_path);
         
         match std::process::Command::new("explorer")
             .arg("/select,")
             .arg(&windows_path)
             .spawn() {
             Ok(_) => {
-                println!("[Platform] Successfully opened explorer with file selected");
+                varo_log!("[Platform] Successfully opened explorer with file selected");
                 return true;
             },
             Err(e) => {
-                println!("[Platform] Failed to launch explorer with /select: {}", e);
+                varo_log!("[Platform] Failed to launch explorer with /select: {}", e);
                 // Fallback to opening the directory
                 if let Some(parent) = file_path.parent() {
                     return open_directory_in_folder(parent);
@@ -157,17 +234,17 @@ pub fn open_file_in_folder(file_path: &Path) -> bool {
     #[cfg(target_os = "macos")]
     {
         // Use -R flag to reveal the file in Finder
-        println!("[Platform] Opening with macOS 'open -R' command");
+        varo_log!("[Platform] Opening with macOS 'open -R' command");
         match std::process::Command::new("open")
             .arg("-R")
             .arg(file_path)
             .spawn() {
             Ok(_) => {
-                println!("[Platform] Successfully revealed file in finder");
+                varo_log!("[Platform] Successfully revealed file in finder");
                 return true;
             },
             Err(e) => {
-                println!("[Platform] Failed to reveal file in finder: {}", e);
+                varo_log!("[Platform] Failed to reveal file in finder: {}", e);
                 // Fallback to opening the directory
                 if let Some(parent) = file_path.parent() {
                     return open_directory_in_folder(parent);
@@ -181,25 +258,25 @@ pub fn open_file_in_folder(file_path: &Path) -> bool {
     {
         // Most Linux file managers don't have a "select file" equivalent
         // Fall back to opening the parent directory
-        println!("[Platform] Linux: Opening parent directory (file selection not supported)");
+        varo_log!("[Platform] Linux: Opening parent directory (file selection not supported)");
         if let Some(parent) = file_path.parent() {
             return open_directory_in_folder(parent);
         } else {
-            println!("[Platform] Unable to get parent directory");
+            varo_log!("[Platform] Unable to get parent directory");
             return false;
         }
     }
 
     #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
     {
-        println!("[Platform] Unsupported platform: {}", env::consts::OS);
+        varo_log!("[Platform] Unsupported platform: {}", env::consts::OS);
         return false;
     }
 }
 
 /// Opens a URL in the system's default browser
 pub fn open_url_in_browser(url: &str) -> bool {
-    println!("[Platform] Attempting to open URL in browser: {}", url);
+    varo_log!("[Platform] Attempting to open URL in browser: {}", url);
 
     #[cfg(target_os = "windows")]
     {
@@ -208,11 +285,11 @@ pub fn open_url_in_browser(url: &str) -> bool {
             .args(["/C", "start", "", url])
             .spawn() {
             Ok(_) => {
-                println!("[Platform] Successfully opened URL in browser via cmd");
+                varo_log!("[Platform] Successfully opened URL in browser via cmd");
                 return true;
             },
             Err(e) => {
-                println!("[Platform] Failed to open URL with cmd: {}", e);
+                varo_log!("[Platform] Failed to open URL with cmd: {}", e);
                 return false;
             }
         }
@@ -225,11 +302,11 @@ pub fn open_url_in_browser(url: &str) -> bool {
             .arg(url)
             .spawn() {
             Ok(_) => {
-                println!("[Platform] Successfully opened URL in browser via open");
+                varo_log!("[Platform] Successfully opened URL in browser via open");
                 return true;
             },
             Err(e) => {
-                println!("[Platform] Failed to open URL with open: {}", e);
+                varo_log!("[Platform] Failed to open URL with open: {}", e);
                 return false;
             }
         }
@@ -240,25 +317,25 @@ pub fn open_url_in_browser(url: &str) -> bool {
         // Try xdg-open first, then common browsers
         let browsers = ["xdg-open", "firefox", "chromium", "google-chrome", "chrome"];
         
-        println!("[Platform] Trying Linux browsers: {:?}", browsers);
+        varo_log!("[Platform] Trying Linux browsers: {:?}", browsers);
         
         for browser in &browsers {
-            println!("[Platform] Trying browser: {}", browser);
+            varo_log!("[Platform] Trying browser: {}", browser);
             if let Ok(_) = std::process::Command::new(browser)
                 .arg(url)
                 .spawn() {
-                println!("[Platform] Successfully opened URL with: {}", browser);
+                varo_log!("[Platform] Successfully opened URL with: {}", browser);
                 return true;
             }
         }
         
-        println!("[Platform] No suitable browser found");
+        varo_log!("[Platform] No suitable browser found");
         return false;
     }
 
     #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
     {
-        println!("[Platform] Unsupported platform for URL opening: {}", env::consts::OS);
+        varo_log!("[Platform] Unsupported platform for URL opening: {}", env::consts::OS);
         return false;
     }
 }
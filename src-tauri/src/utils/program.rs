@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::utils::platform::get_path_separator;
+
+/// How a command `path` is interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathType {
+    /// Relative to the node file, then `VARO_PATH`; bare names are looked up on `PATH`
+    Rel,
+    /// Used as-is
+    Abs,
+    /// Opened in the default browser
+    Url,
+}
+
+impl PathType {
+    /// Parses a `path_type` value. Returns `None` for empty or unknown values.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "rel" => Some(PathType::Rel),
+            "abs" => Some(PathType::Abs),
+            "url" => Some(PathType::Url),
+            _ => None,
+        }
+    }
+
+    /// Infers the type of a path: URL schemes are `url`, absolute paths `abs`, anything else `rel`
+    pub fn infer(path: &str) -> Self {
+        if has_url_scheme(path) {
+            PathType::Url
+        } else if Path::new(path).is_absolute() {
+            PathType::Abs
+        } else {
+            PathType::Rel
+        }
+    }
+
+    /// The declared type if valid, otherwise the type inferred from `path`
+    pub fn from_command(path_type: &str, path: &str) -> Self {
+        Self::parse(path_type).unwrap_or_else(|| Self::infer(path))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PathType::Rel => "rel",
+            PathType::Abs => "abs",
+            PathType::Url => "url",
+        }
+    }
+}
+
+/// True for strings like `https://...` or `file://...`.
+/// Single-letter schemes are left out so Windows drive paths (`C://Tools`) are not taken for URLs.
+pub fn has_url_scheme(path: &str) -> bool {
+    match path.find("://") {
+        Some(index) if index > 1 => {
+            let scheme = &path[..index];
+            scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+/// True when `path` is a plain program name without any directory part (e.g. `python`)
+pub fn is_bare_name(path: &str) -> bool {
+    !path.is_empty() && !path.contains('/') && !path.contains('\\') && path != "." && path != ".."
+}
+
+/// Where relative program paths are looked up
+#[derive(Debug, Default)]
+pub struct ProgramSearch<'a> {
+    /// Folder containing the node file
+    pub node_dir: Option<&'a Path>,
+    /// Fallback root for relative paths not found next to the node file
+    pub varo_path: Option<&'a str>,
+    /// Effective `PATH` used for bare names
+    pub path_list: Option<&'a str>,
+    /// Effective `PATHEXT` (Windows only)
+    pub path_ext: Option<&'a str>,
+}
+
+impl<'a> ProgramSearch<'a> {
+    /// Builds the search locations from an environment map, reading `PATH` and `PATHEXT`
+    pub fn from_env(node_dir: Option<&'a Path>, varo_path: Option<&'a str>, env: &'a HashMap<String, String>) -> Self {
+        Self {
+            node_dir,
+            varo_path,
+            path_list: env_value(env, "PATH").map(|s| s.as_str()),
+            path_ext: env_value(env, "PATHEXT").map(|s| s.as_str()),
+        }
+    }
+}
+
+/// Looks up an environment variable, ignoring case on Windows where `Path` and `PATH` are the same variable
+fn env_value<'a>(env: &'a HashMap<String, String>, name: &str) -> Option<&'a String> {
+    if cfg!(target_os = "windows") {
+        env.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value)
+    } else {
+        env.get(name)
+    }
+}
+
+/// Resolves the program to launch for a non-URL command path.
+///
+/// - `abs` paths must be absolute and are used as-is.
+/// - `rel` paths are tried next to the node file, then under `varo_path`.
+///   Bare names are looked up on `PATH` first, like a shell would.
+pub fn resolve_program_path(path: &str, path_type: PathType, search: &ProgramSearch) -> Result<PathBuf, String> {
+    match path_type {
+        PathType::Url => Err(format!("Cannot launch URL as a program: {}", path)),
+        PathType::Abs => {
+            let program = PathBuf::from(path);
+            if program.is_absolute() {
+                Ok(program)
+            } else {
+                Err(format!("Path type 'abs' requires an absolute path: {}", path))
+            }
+        }
+        PathType::Rel => {
+            if Path::new(path).is_absolute() {
+                return Ok(PathBuf::from(path));
+            }
+
+            let mut searched = Vec::new();
+            if is_bare_name(path) {
+                if let Some(list) = search.path_list {
+                    if let Some(found) = find_on_path(path, list, search.path_ext) {
+                        return Ok(found);
+                    }
+                    searched.push("PATH".to_string());
+                }
+            }
+
+            let roots = [search.node_dir.map(Path::to_path_buf), search.varo_path.map(PathBuf::from)];
+            for root in roots.into_iter().flatten() {
+                let candidate = root.join(path);
+                if candidate.is_file() {
+                    return Ok(candidate);
+                }
+                searched.push(root.display().to_string());
+            }
+
+            if searched.is_empty() {
+                Err(format!("Cannot resolve relative path '{}': no node folder or VARO_PATH to search", path))
+            } else {
+                Err(format!("Program '{}' not found (searched: {})", path, searched.join(", ")))
+            }
+        }
+    }
+}
+
+/// Finds an executable by name in a `PATH`-style list of directories.
+/// On Windows names without an extension are also tried with each `PATHEXT` extension.
+pub fn find_on_path(name: &str, path_list: &str, path_ext: Option<&str>) -> Option<PathBuf> {
+    let extensions: Vec<String> = if cfg!(target_os = "windows") && Path::new(name).extension().is_none() {
+        path_ext.unwrap_or(".COM;.EXE;.BAT;.CMD")
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| ext.to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    path_list
+        .split(get_path_separator())
+        .filter(|dir| !dir.is_empty())
+        .find_map(|dir| {
+            let base = Path::new(dir).join(name);
+            if is_executable(&base) {
+                return Some(base);
+            }
+            extensions.iter()
+                .map(|ext| Path::new(dir).join(format!("{}{}", name, ext)))
+                .find(|candidate| is_executable(candidate))
+        })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -15,20 +15,26 @@ export class EnvPreset {
   name: string;
   filepath: string;
   description: string;
+  extends: string[];
   env: EnvVar[];
+  flattenedEnv: EnvVar[];
 
   constructor(data: {
     id: string;
     name: string;
     filepath: string;
     description?: string;
+    extends?: string[];
     env?: { name: string; value: string; operation?: string }[];
+    flattened_env?: { name: string; value: string; operation?: string }[];
   }) {
     this.id = data.id;
     this.name = data.name;
     this.filepath = data.filepath;
     this.description = data.description ?? '';
+    this.extends = data.extends ?? [];
     this.env = (data.env ?? []).map(e => new EnvVar(e));
+    this.flattenedEnv = (data.flattened_env ?? data.env ?? []).map(e => new EnvVar(e));
   }
 
   get isEmpty(): boolean {